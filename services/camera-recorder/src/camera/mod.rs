@@ -1,2 +1,4 @@
 pub mod ffmpeg;
+pub mod probe;
 pub mod recorder;
+pub mod status;
@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Media properties of a recorded segment, as reported by ffprobe
+#[derive(Debug, Clone, Default)]
+pub struct MediaInfo {
+    pub video_codec: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration_secs: Option<f64>,
+}
+
+impl MediaInfo {
+    /// Resolution as `WIDTHxHEIGHT`, when known
+    pub fn resolution(&self) -> Option<String> {
+        Some(format!("{}x{}", self.width?, self.height?))
+    }
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+/// Probe a media file's first video stream and container duration
+pub async fn probe_media(path: &Path) -> Result<MediaInfo> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=codec_name,width,height:format=duration",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+        .await
+        .context("Failed to run ffprobe")?;
+
+    anyhow::ensure!(
+        output.status.success(),
+        "ffprobe exited with code {:?}: {}",
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let probe: ProbeOutput =
+        serde_json::from_slice(&output.stdout).context("Failed to parse ffprobe output")?;
+    let stream = probe.streams.into_iter().next();
+
+    Ok(MediaInfo {
+        video_codec: stream.as_ref().and_then(|s| s.codec_name.clone()),
+        width: stream.as_ref().and_then(|s| s.width),
+        height: stream.as_ref().and_then(|s| s.height),
+        duration_secs: probe
+            .format
+            .and_then(|f| f.duration)
+            .and_then(|d| d.parse().ok()),
+    })
+}
@@ -1,25 +1,40 @@
-use crate::config::{CameraConfig, RecordingConfig, UploadConfig};
+use super::ffmpeg::{FfmpegCommand, Progress, SegmentListEntry};
+use super::status::CameraState;
+use crate::config::{CameraConfig, ReconnectConfig, RecordingConfig};
 use crate::metrics;
-use crate::storage::SegmentInfo;
+use crate::storage::{SegmentInfo, UploadQueue};
 use crate::ServiceState;
 use anyhow::{Context, Result};
 use chrono::Utc;
 
-use regex::Regex;
+use std::collections::HashSet;
+use std::io::SeekFrom;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::sync::{mpsc, RwLock};
-use tokio::time::{sleep, Duration};
+use std::time::SystemTime;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin};
+use tokio::sync::{oneshot, watch, RwLock};
+use tokio::time::{interval, sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
-/// Run recorder for a single camera
+/// FFmpeg's list of closed segments, kept next to them in the camera's temp dir
+const SEGMENT_LIST_FILE: &str = ".segments.csv";
+/// How often the segment list is checked for newly closed segments
+const SEGMENT_LIST_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long FFmpeg gets to write the trailer after being asked to quit
+const FFMPEG_QUIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run recorder for a single camera until `shutdown` is cancelled,
+/// reconnecting after failures as `reconnect` describes
 pub async fn run_recorder(
     camera: CameraConfig,
     recording: RecordingConfig,
-    _upload: UploadConfig,
-    upload_tx: mpsc::Sender<SegmentInfo>,
+    reconnect: ReconnectConfig,
+    upload_queue: UploadQueue,
     state: Arc<RwLock<ServiceState>>,
+    shutdown: CancellationToken,
 ) -> Result<()> {
     info!(camera_id = %camera.id, "Starting camera recorder");
 
@@ -29,263 +44,540 @@ pub async fn run_recorder(
         .await
         .context("Failed to create temp directory")?;
 
-    let mut retry_count = 0;
-    let max_retries = 5;
-    let mut backoff_secs = 1u64;
+    let healthy_after = Duration::from_secs(reconnect.healthy_after_secs);
+    let mut failures = 0u32;
+    let mut backoff_secs = reconnect.initial_backoff_secs;
 
     loop {
-        match run_recording_session(&camera, &recording, &camera_temp_dir, &state, &upload_tx).await
-        {
+        let started = Instant::now();
+        let session = run_recording_session(
+            &camera,
+            &recording,
+            &camera_temp_dir,
+            &state,
+            &upload_queue,
+            &shutdown,
+        )
+        .await;
+        if shutdown.is_cancelled() {
+            state
+                .write()
+                .await
+                .set_camera_state(&camera.id, CameraState::Stopped);
+            info!(camera_id = %camera.id, "Camera recorder stopped");
+            return Ok(());
+        }
+
+        // A session that stayed up long enough wipes the slate
+        let healthy = started.elapsed() >= healthy_after;
+        if healthy {
+            if is_degraded(&reconnect, failures) {
+                info!(camera_id = %camera.id, "Camera recovered from degraded state");
+            }
+            failures = 0;
+            backoff_secs = reconnect.initial_backoff_secs;
+            if let Some(status) = state.write().await.cameras.get_mut(&camera.id) {
+                status.consecutive_failures = 0;
+            }
+            metrics::CAMERA_CONSECUTIVE_FAILURES
+                .with_label_values(&[&camera.id])
+                .set(0.0);
+        }
+
+        let error = match session {
             Ok(()) => {
                 warn!(camera_id = %camera.id, "Recording session ended normally");
-                retry_count = 0;
-                backoff_secs = 1;
+                if healthy {
+                    state
+                        .write()
+                        .await
+                        .set_camera_state(&camera.id, CameraState::Starting);
+                    continue;
+                }
+                format!("Session ended after {:?}", started.elapsed())
             }
             Err(e) => {
                 error!(
                     camera_id = %camera.id,
                     error = %e,
-                    retry = retry_count,
                     "Recording session failed"
                 );
-
-                // Update connection state and metrics
-                {
-                    let mut s = state.write().await;
-                    if s.cameras_connected > 0 {
-                        s.cameras_connected -= 1;
-                    }
-                }
-                metrics::CAMERA_CONNECTED
-                    .with_label_values(&[&camera.id])
-                    .set(0.0);
                 metrics::FFMPEG_RESTARTS
                     .with_label_values(&[&camera.id])
                     .inc();
+                format!("{e:#}")
+            }
+        };
 
-                retry_count += 1;
-                if retry_count >= max_retries {
-                    error!(
-                        camera_id = %camera.id,
-                        "Max retries exceeded, resetting backoff"
-                    );
-                    retry_count = 0;
-                    backoff_secs = 1;
-                }
-
-                // Exponential backoff
-                info!(
+        failures += 1;
+        let degraded = is_degraded(&reconnect, failures);
+        let wait = if degraded {
+            if failures == reconnect.degraded_after_failures {
+                warn!(
                     camera_id = %camera.id,
-                    wait_secs = backoff_secs,
-                    "Waiting before retry"
+                    failures,
+                    probe_secs = reconnect.degraded_probe_secs,
+                    "Camera degraded, probing less often"
                 );
-                sleep(Duration::from_secs(backoff_secs)).await;
-                backoff_secs = (backoff_secs * 2).min(60); // Max 60 second backoff
+            }
+            jittered(reconnect.degraded_probe_secs, reconnect.backoff_jitter)
+        } else {
+            let wait = jittered(backoff_secs, reconnect.backoff_jitter);
+            backoff_secs = (backoff_secs * 2).min(reconnect.max_backoff_secs);
+            wait
+        };
+
+        {
+            let mut s = state.write().await;
+            let status = s.set_camera_state(
+                &camera.id,
+                if degraded {
+                    CameraState::Degraded
+                } else {
+                    CameraState::Backoff
+                },
+            );
+            status.consecutive_failures = failures;
+            status.last_error = Some(error);
+            status.next_attempt_at = chrono::Duration::from_std(wait)
+                .ok()
+                .map(|wait| Utc::now() + wait);
+        }
+        metrics::CAMERA_CONSECUTIVE_FAILURES
+            .with_label_values(&[&camera.id])
+            .set(failures as f64);
+
+        info!(
+            camera_id = %camera.id,
+            failures,
+            wait_secs = wait.as_secs_f64(),
+            "Waiting before retry"
+        );
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = shutdown.cancelled() => {
+                state
+                    .write()
+                    .await
+                    .set_camera_state(&camera.id, CameraState::Stopped);
+                info!(camera_id = %camera.id, "Camera recorder stopped");
+                return Ok(());
             }
         }
     }
 }
 
-/// Run a single recording session (until error or signal)
+/// Whether `failures` fast failures in a row trip the circuit breaker
+fn is_degraded(reconnect: &ReconnectConfig, failures: u32) -> bool {
+    reconnect.degraded_after_failures > 0 && failures >= reconnect.degraded_after_failures
+}
+
+/// `secs`, spread randomly by up to `jitter` of it either way so cameras
+/// that failed together don't reconnect in lockstep
+fn jittered(secs: u64, jitter: f64) -> Duration {
+    let spread = if jitter > 0.0 {
+        rand::random_range(-jitter..=jitter)
+    } else {
+        0.0
+    };
+    Duration::from_secs(secs).mul_f64(1.0 + spread)
+}
+
+/// Why a recording session ended
+enum SessionEnd {
+    Exited(std::process::ExitStatus),
+    Stalled,
+    Shutdown,
+}
+
+/// Run a single recording session (until error or shutdown)
 async fn run_recording_session(
     camera: &CameraConfig,
     recording: &RecordingConfig,
     temp_dir: &Path,
     state: &Arc<RwLock<ServiceState>>,
-    upload_tx: &mpsc::Sender<SegmentInfo>,
+    upload_queue: &UploadQueue,
+    shutdown: &CancellationToken,
 ) -> Result<()> {
     info!(camera_id = %camera.id, "Starting recording session");
 
     // Build output pattern for FFmpeg segmentation
     let output_pattern = temp_dir.join(format!("%Y%m%d_%H%M%S_{}.mp4", camera.id));
 
-    let mut cmd = tokio::process::Command::new("ffmpeg");
-    cmd.args([
-        "-rtsp_transport",
-        "tcp",
-        "-i",
-        &camera.rtsp_url,
-        "-c:v",
-        &recording.video_codec,
-        "-c:a",
-        &recording.audio_codec,
-        "-f",
-        "segment",
-        "-segment_time",
-        &camera.segment_duration_secs.to_string(),
-        "-segment_format",
-        "mp4",
-        "-segment_format_options",
-        "movflags=+frag_keyframe+empty_moov+default_base_moof",  // Enable streaming while recording
-        "-strftime",
-        "1",
-        "-reset_timestamps",
-        "1",
-        "-y", // Overwrite files
+    // FFmpeg truncates the list when it starts; remove it first so a
+    // previous session's entries are never read again
+    let segment_list = temp_dir.join(SEGMENT_LIST_FILE);
+    match tokio::fs::remove_file(&segment_list).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context("Failed to remove stale segment list"),
+    }
+
+    let mut cmd = FfmpegCommand::for_camera(
+        camera,
+        recording,
         output_pattern.to_str().context("Invalid output path")?,
-    ]);
+        segment_list.to_str().context("Invalid segment list path")?,
+    )
+    .command();
 
     info!(camera_id = %camera.id, "Starting FFmpeg process");
 
-    // Spawn FFmpeg with piped stderr
+    let started_at = SystemTime::now();
+
+    // Spawn FFmpeg with commands on stdin, progress on stdout and logs on
+    // stderr; it must not outlive the service if shutdown runs out of time
     let mut child = cmd
-        .stdout(std::process::Stdio::null())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
         .spawn()
         .context("Failed to spawn FFmpeg")?;
 
     // Mark camera as connected
-    {
-        let mut s = state.write().await;
-        s.cameras_connected += 1;
-    }
-    metrics::CAMERA_CONNECTED
-        .with_label_values(&[&camera.id])
-        .set(1.0);
+    state
+        .write()
+        .await
+        .set_camera_state(&camera.id, CameraState::Recording);
 
     info!(camera_id = %camera.id, "Camera connected, recording started");
 
-    // Read stderr in background to detect segments
+    // Stderr is only logged; finished segments come from the segment list
     let stderr = child.stderr.take().context("Failed to get stderr")?;
-    let camera_id = camera.id.clone();
-    let upload_tx_clone = upload_tx.clone();
-    let temp_dir_clone = temp_dir.to_path_buf();
-
-    let stderr_task = tokio::spawn(async move {
-        parse_ffmpeg_stderr(stderr, camera_id, temp_dir_clone, upload_tx_clone).await;
-    });
-
-    // Wait for FFmpeg to complete
-    let status = child.wait().await.context("FFmpeg process error")?;
+    let stderr_task = tokio::spawn(log_ffmpeg_stderr(stderr, camera.id.clone()));
+
+    // Held here because `Child::wait` closes stdin, which would leave no
+    // way to ask FFmpeg to quit
+    let stdin = child.stdin.take().context("Failed to get stdin")?;
+
+    let stdout = child.stdout.take().context("Failed to get stdout")?;
+    let (progress_tx, progress) = watch::channel(Progress::default());
+    tokio::spawn(track_progress(stdout, progress_tx));
+
+    let (stop_watching, stop) = oneshot::channel();
+    let watch_task = tokio::spawn(watch_segment_list(
+        SegmentListWatcher::new(segment_list),
+        camera.clone(),
+        temp_dir.to_path_buf(),
+        upload_queue.clone(),
+        stop,
+    ));
+
+    // Wait for FFmpeg to complete, kill it if it stops making progress and
+    // stop it cleanly on shutdown
+    let stall_timeout = Duration::from_secs(recording.stall_timeout_secs);
+    let end = tokio::select! {
+        status = child.wait() => SessionEnd::Exited(status.context("FFmpeg process error")?),
+        () = stall_watchdog(progress, stall_timeout) => SessionEnd::Stalled,
+        () = shutdown.cancelled() => SessionEnd::Shutdown,
+    };
+    match end {
+        SessionEnd::Exited(_) => {}
+        SessionEnd::Stalled => {
+            warn!(
+                camera_id = %camera.id,
+                stall_timeout_secs = recording.stall_timeout_secs,
+                "FFmpeg stalled, restarting session"
+            );
+            metrics::FFMPEG_STALLS
+                .with_label_values(&[&camera.id])
+                .inc();
+            child
+                .kill()
+                .await
+                .context("Failed to kill stalled FFmpeg")?;
+        }
+        SessionEnd::Shutdown => stop_ffmpeg(&mut child, stdin, &camera.id).await,
+    }
 
-    // Wait for stderr parsing to finish
+    // FFmpeg lists the last segment when it writes the trailer, so pick up
+    // whatever it appended before exiting
+    let _ = stop_watching.send(());
+    let listed = watch_task.await.unwrap_or_default();
     let _ = stderr_task.await;
 
-    // Update connection state
-    {
-        let mut s = state.write().await;
-        if s.cameras_connected > 0 {
-            s.cameras_connected -= 1;
+    queue_unlisted_segments(camera, temp_dir, upload_queue, started_at, &listed).await;
+
+    match end {
+        SessionEnd::Exited(status) if !status.success() => {
+            anyhow::bail!("FFmpeg exited with code: {:?}", status.code())
         }
+        SessionEnd::Stalled => anyhow::bail!("FFmpeg made no progress for {stall_timeout:?}"),
+        SessionEnd::Exited(_) | SessionEnd::Shutdown => Ok(()),
     }
-    metrics::CAMERA_CONNECTED
-        .with_label_values(&[&camera.id])
-        .set(0.0);
+}
 
-    if !status.success() {
-        anyhow::bail!("FFmpeg exited with code: {:?}", status.code());
+/// Ask FFmpeg to quit, so it closes the current segment with a trailer and
+/// lists it, killing it if it takes too long
+async fn stop_ffmpeg(child: &mut Child, mut stdin: ChildStdin, camera_id: &str) {
+    info!(camera_id = %camera_id, "Stopping FFmpeg");
+    // `q` is FFmpeg's interactive quit command
+    if let Err(e) = stdin.write_all(b"q").await {
+        warn!(camera_id = %camera_id, error = %e, "Failed to ask FFmpeg to quit");
+    }
+    drop(stdin);
+    match tokio::time::timeout(FFMPEG_QUIT_TIMEOUT, child.wait()).await {
+        Ok(Ok(status)) => info!(camera_id = %camera_id, code = ?status.code(), "FFmpeg stopped"),
+        Ok(Err(e)) => warn!(camera_id = %camera_id, error = %e, "Failed to wait for FFmpeg"),
+        Err(_) => {
+            warn!(camera_id = %camera_id, "FFmpeg did not quit in time, killing it");
+            if let Err(e) = child.kill().await {
+                warn!(camera_id = %camera_id, error = %e, "Failed to kill FFmpeg");
+            }
+        }
     }
-
-    Ok(())
 }
 
-/// Parse FFmpeg stderr to detect completed segments
-async fn parse_ffmpeg_stderr(
-    stderr: impl tokio::io::AsyncRead + Unpin,
-    camera_id: String,
-    temp_dir: PathBuf,
-    upload_tx: mpsc::Sender<SegmentInfo>,
+/// Publish FFmpeg's `-progress` reports whenever frames or bytes were written
+async fn track_progress(
+    stdout: impl tokio::io::AsyncRead + Unpin,
+    progress: watch::Sender<Progress>,
 ) {
-    let reader = BufReader::new(stderr);
-    let mut lines = reader.lines();
+    let mut lines = BufReader::new(stdout).lines();
+    let mut current = Progress::default();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if current.update(&line) {
+            progress.send_if_modified(|last| {
+                let advanced = current.advanced_since(last);
+                if advanced {
+                    *last = current;
+                }
+                advanced
+            });
+        }
+    }
+}
 
-    // Regex to match segment opening lines
-    // Example: [segment @ 0x...] Opening 'output_20251214_013000_camera-1.mp4' for writing
-    let segment_regex = Regex::new(r"Opening '([^']+)' for writing").unwrap();
+/// Resolves once `progress` hasn't advanced for `timeout` (never if zero).
+///
+/// The session start counts as progress, so a camera that accepts the
+/// connection but never sends a frame is caught too.
+async fn stall_watchdog(mut progress: watch::Receiver<Progress>, timeout: Duration) {
+    if timeout.is_zero() {
+        return std::future::pending().await;
+    }
+    loop {
+        match tokio::time::timeout(timeout, progress.changed()).await {
+            Ok(Ok(())) => {}
+            // FFmpeg closed stdout, so it is exiting
+            Ok(Err(_)) => return std::future::pending().await,
+            Err(_) => return,
+        }
+    }
+}
 
-    let mut current_segment: Option<String> = None;
+/// Follows a CSV segment list as FFmpeg appends to it
+struct SegmentListWatcher {
+    path: PathBuf,
+    offset: u64,
+    /// Trailing line FFmpeg hasn't finished writing yet
+    partial: String,
+}
 
-    while let Ok(Some(line)) = lines.next_line().await {
-        // Log FFmpeg output for debugging
-        if line.contains("error") || line.contains("Error") {
-            error!(camera_id = %camera_id, ffmpeg_output = %line, "FFmpeg error");
+impl SegmentListWatcher {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            offset: 0,
+            partial: String::new(),
         }
+    }
 
-        // Detect new segment
-        if let Some(caps) = segment_regex.captures(&line) {
-            if let Some(filename_match) = caps.get(1) {
-                let segment_filename = filename_match.as_str();
-
-                // If we have a previous segment, it's now complete - queue for upload
-                if let Some(prev_segment) = current_segment.take() {
-                    let segment_path = temp_dir.join(&prev_segment);
-
-                    // Check if file exists and get size
-                    if let Ok(metadata) = tokio::fs::metadata(&segment_path).await {
-                        let size_bytes = metadata.len();
-
-                        info!(
-                            camera_id = %camera_id,
-                            segment = %prev_segment,
-                            size_mb = size_bytes / 1_048_576,
-                            "Segment completed, queuing for upload"
-                        );
-
-                        metrics::SEGMENTS_RECORDED
-                            .with_label_values(&[&camera_id])
-                            .inc();
-                        metrics::RECORDING_BYTES
-                            .with_label_values(&[&camera_id])
-                            .inc_by(size_bytes as f64);
-
-                        let segment_info = SegmentInfo {
-                            camera_id: camera_id.clone(),
-                            local_path: segment_path,
-                            timestamp: Utc::now(),
-                        };
-
-                        if let Err(e) = upload_tx.send(segment_info).await {
-                            error!(error = %e, "Failed to send segment to upload queue");
-                        }
-                    }
+    /// Entries appended since the last call
+    async fn poll(&mut self) -> Result<Vec<SegmentListEntry>> {
+        let mut file = match tokio::fs::File::open(&self.path).await {
+            Ok(file) => file,
+            // Not created until FFmpeg has opened its output
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to open segment list"),
+        };
+        if file.metadata().await?.len() < self.offset {
+            // Truncated, so FFmpeg started over
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset)).await?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended).await?;
+        self.offset += appended.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&appended));
+
+        let Some(end) = self.partial.rfind('\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: String = self.partial.drain(..=end).collect();
+        Ok(complete
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                let entry = SegmentListEntry::parse(line);
+                if entry.is_none() {
+                    warn!(path = %self.path.display(), line = %line, "Unrecognized segment list line");
                 }
+                entry
+            })
+            .collect())
+    }
+}
 
-                // Track new segment
-                current_segment = Some(
-                    PathBuf::from(segment_filename)
-                        .file_name()
-                        .unwrap()
-                        .to_string_lossy()
-                        .to_string(),
-                );
-
-                info!(
-                    camera_id = %camera_id,
-                    segment = %current_segment.as_ref().unwrap(),
-                    "Started new segment"
-                );
+/// Queue every segment FFmpeg lists as closed until `stop` fires, returning
+/// the filenames seen
+async fn watch_segment_list(
+    mut watcher: SegmentListWatcher,
+    camera: CameraConfig,
+    temp_dir: PathBuf,
+    upload_queue: UploadQueue,
+    mut stop: oneshot::Receiver<()>,
+) -> HashSet<String> {
+    let mut listed = HashSet::new();
+    let mut ticker = interval(SEGMENT_LIST_POLL_INTERVAL);
+    loop {
+        let stopping = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = &mut stop => true,
+        };
+        match watcher.poll().await {
+            Ok(entries) => {
+                for entry in entries {
+                    // Entries are relative to the list unless a prefix is set
+                    let Some(filename) = Path::new(&entry.filename).file_name() else {
+                        continue;
+                    };
+                    let path = temp_dir.join(filename);
+                    listed.insert(filename.to_string_lossy().to_string());
+                    queue_segment(&camera, &upload_queue, path, Some(entry.duration_secs())).await;
+                }
             }
+            Err(e) => warn!(camera_id = %camera.id, error = %e, "Failed to read segment list"),
+        }
+        if stopping {
+            return listed;
         }
     }
+}
 
-    // Handle last segment if stream ends
-    if let Some(last_segment) = current_segment {
-        let segment_path = temp_dir.join(&last_segment);
-
-        if let Ok(metadata) = tokio::fs::metadata(&segment_path).await {
-            let size_bytes = metadata.len();
+/// Queue this session's segments that never made it into the list, e.g.
+/// because FFmpeg was killed before closing them
+async fn queue_unlisted_segments(
+    camera: &CameraConfig,
+    temp_dir: &Path,
+    upload_queue: &UploadQueue,
+    started_at: SystemTime,
+    listed: &HashSet<String>,
+) {
+    let mut entries = match tokio::fs::read_dir(temp_dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!(camera_id = %camera.id, error = %e, "Failed to scan for unlisted segments");
+            return;
+        }
+    };
+    let mut unlisted = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if listed.contains(&name) || SegmentInfo::from_file(camera, &entry.path()).is_none() {
+            continue;
+        }
+        let from_this_session = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .map(|modified| modified >= started_at)
+            .unwrap_or(false);
+        if from_this_session {
+            unlisted.push(entry.path());
+        }
+    }
+    unlisted.sort();
+    for path in unlisted {
+        warn!(
+            camera_id = %camera.id,
+            path = %path.display(),
+            "Segment missing from FFmpeg's segment list, queuing it anyway"
+        );
+        // Duration is left to the upload's ffprobe
+        queue_segment(camera, upload_queue, path, None).await;
+    }
+}
 
-            info!(
-                camera_id = %camera_id,
-                segment = %last_segment,
-                size_mb = size_bytes / 1_048_576,
-                "Final segment completed"
+/// Hand a closed segment to the upload queue
+async fn queue_segment(
+    camera: &CameraConfig,
+    upload_queue: &UploadQueue,
+    path: PathBuf,
+    duration_secs: Option<f64>,
+) {
+    let size_bytes = match tokio::fs::metadata(&path).await {
+        Ok(metadata) if metadata.len() > 0 => metadata.len(),
+        Ok(_) => return,
+        Err(e) => {
+            warn!(
+                camera_id = %camera.id,
+                path = %path.display(),
+                error = %e,
+                "Listed segment not found"
             );
+            return;
+        }
+    };
 
-            metrics::SEGMENTS_RECORDED
-                .with_label_values(&[&camera_id])
-                .inc();
-            metrics::RECORDING_BYTES
-                .with_label_values(&[&camera_id])
-                .inc_by(size_bytes as f64);
+    info!(
+        camera_id = %camera.id,
+        segment = %path.display(),
+        size_mb = size_bytes / 1_048_576,
+        duration_secs,
+        "Segment completed, queuing for upload"
+    );
 
-            let segment_info = SegmentInfo {
-                camera_id: camera_id.clone(),
-                local_path: segment_path,
-                timestamp: Utc::now(),
-            };
+    metrics::SEGMENTS_RECORDED
+        .with_label_values(&[&camera.id])
+        .inc();
+    metrics::RECORDING_BYTES
+        .with_label_values(&[&camera.id])
+        .inc_by(size_bytes as f64);
 
-            let _ = upload_tx.send(segment_info).await;
+    let segment_info = completed_segment(camera, path, duration_secs);
+    if let Err(e) = upload_queue.enqueue(segment_info).await {
+        error!(error = %e, "Failed to send segment to upload queue");
+    }
+}
+
+/// Segment info for a finished file, timed from its name and listed duration
+fn completed_segment(
+    camera: &CameraConfig,
+    path: PathBuf,
+    duration_secs: Option<f64>,
+) -> SegmentInfo {
+    let segment = SegmentInfo::from_file(camera, &path).unwrap_or_else(|| {
+        // Only happens if the output pattern and the parser disagree
+        warn!(
+            camera_id = %camera.id,
+            path = %path.display(),
+            "Segment filename has no start time, estimating it"
+        );
+        let duration = duration_secs.unwrap_or(camera.segment_duration_secs as f64);
+        SegmentInfo {
+            camera_id: camera.id.clone(),
+            camera_name: camera.name.clone(),
+            local_path: path,
+            start: Utc::now() - chrono::Duration::milliseconds((duration * 1000.0) as i64),
+            end: None,
+            duration_secs: None,
+        }
+    });
+    match duration_secs {
+        Some(secs) => segment.with_duration(secs),
+        None => segment,
+    }
+}
+
+/// Log FFmpeg's stderr for diagnostics
+async fn log_ffmpeg_stderr(stderr: impl tokio::io::AsyncRead + Unpin, camera_id: String) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.contains("error") || line.contains("Error") {
+            error!(camera_id = %camera_id, ffmpeg_output = %line, "FFmpeg error");
         }
     }
 }
@@ -0,0 +1,78 @@
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// What a camera's recorder is currently doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CameraState {
+    /// Not recording yet
+    Starting,
+    /// FFmpeg is running
+    Recording,
+    /// Waiting to reconnect after a failed session
+    Backoff,
+    /// Failing repeatedly, so only probed every `degraded_probe_secs`
+    Degraded,
+    /// Shut down
+    Stopped,
+}
+
+impl CameraState {
+    const ALL: [CameraState; 5] = [
+        Self::Starting,
+        Self::Recording,
+        Self::Backoff,
+        Self::Degraded,
+        Self::Stopped,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Starting => "starting",
+            Self::Recording => "recording",
+            Self::Backoff => "backoff",
+            Self::Degraded => "degraded",
+            Self::Stopped => "stopped",
+        }
+    }
+
+    /// Set the per-state gauges so exactly this state reads 1
+    pub fn publish(self, camera_id: &str) {
+        for state in Self::ALL {
+            metrics::CAMERA_STATE
+                .with_label_values(&[camera_id, state.as_str()])
+                .set(if state == self { 1.0 } else { 0.0 });
+        }
+        metrics::CAMERA_CONNECTED
+            .with_label_values(&[camera_id])
+            .set(if self == Self::Recording { 1.0 } else { 0.0 });
+    }
+}
+
+/// Reconnect status of one camera, as reported by `/status`
+#[derive(Debug, Clone, Serialize)]
+pub struct CameraStatus {
+    pub state: CameraState,
+    /// When `state` was entered
+    pub since: DateTime<Utc>,
+    /// Sessions in a row that ended before becoming healthy
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// When the next connection attempt is due, while waiting for one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl Default for CameraStatus {
+    fn default() -> Self {
+        Self {
+            state: CameraState::Starting,
+            since: Utc::now(),
+            consecutive_failures: 0,
+            last_error: None,
+            next_attempt_at: None,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::config::Config;
+use crate::storage;
+use crate::storage::dead_letter::DeadLetter;
+use crate::storage::encryption::{self, Keyring};
+use anyhow::{Context, Result};
+use std::path::Path;
+
+const USAGE: &str = "Usage:
+  camera-recorder decrypt <file-or-object-key> <output>
+  camera-recorder dead-letter list
+  camera-recorder dead-letter redrive [<camera_id>/<filename>]";
+
+/// Run a one-off subcommand instead of the recorder
+pub async fn run(config: &Config, args: &[String]) -> Result<()> {
+    match args {
+        [command, input, output] if command == "decrypt" => {
+            decrypt(config, input, Path::new(output)).await
+        }
+        [command, action] if command == "dead-letter" && action == "list" => {
+            dead_letter_list(config).await
+        }
+        [command, action, rest @ ..] if command == "dead-letter" && action == "redrive" => {
+            match rest {
+                [] => dead_letter_redrive(config, "/dead-letter/redrive").await,
+                [id] => {
+                    let path = format!("/dead-letter/{}/redrive", id.trim_matches('/'));
+                    dead_letter_redrive(config, &path).await
+                }
+                _ => anyhow::bail!(USAGE),
+            }
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+}
+
+/// Base URL of the running recorder's HTTP server (`RECORDER_URL` overrides)
+fn recorder_url(config: &Config) -> String {
+    std::env::var("RECORDER_URL")
+        .unwrap_or_else(|_| format!("http://127.0.0.1:{}", config.service.metrics_port))
+}
+
+async fn dead_letter_list(config: &Config) -> Result<()> {
+    let dead_letters: Vec<DeadLetter> =
+        reqwest::get(format!("{}/dead-letter", recorder_url(config)))
+            .await
+            .context("Failed to reach the recorder")?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Failed to parse dead letters")?;
+
+    if dead_letters.is_empty() {
+        println!("No dead-lettered segments");
+    }
+    for dead_letter in dead_letters {
+        println!(
+            "{}\t{} MB\tfailed {}\tredrives {}\t{}",
+            dead_letter.id(),
+            dead_letter.size_bytes / 1_048_576,
+            dead_letter.failed_at.format("%Y-%m-%d %H:%M:%S"),
+            dead_letter.redrives,
+            dead_letter.error
+        );
+    }
+    Ok(())
+}
+
+/// Ask the running recorder to re-drive, since only it can queue uploads
+async fn dead_letter_redrive(config: &Config, path: &str) -> Result<()> {
+    let mut request = reqwest::Client::new().post(format!("{}{path}", recorder_url(config)));
+    if let Some(token) = &config.service.admin_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request
+        .send()
+        .await
+        .context("Failed to reach the recorder")?;
+    let status = response.status();
+    let body = response.text().await?;
+    anyhow::ensure!(status.is_success(), "Re-drive failed: {body}");
+    println!("{body}");
+    Ok(())
+}
+
+/// Decrypt a segment for playback or export. `input` is either a local
+/// encrypted file or an object key on the primary destination.
+async fn decrypt(config: &Config, input: &str, output: &Path) -> Result<()> {
+    let keyring = Keyring::load(&config.encryption).context("Failed to load encryption keys")?;
+
+    if Path::new(input).is_file() {
+        return encryption::decrypt_file(&keyring, Path::new(input), output);
+    }
+
+    let backend = storage::backend::connect(&config.storage.primary, &config.upload).await?;
+    encryption::decrypt_object(&keyring, backend.as_ref(), input, output).await
+}
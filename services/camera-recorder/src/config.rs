@@ -1,4 +1,7 @@
+use crate::storage::key_template::{KeyLayout, DEFAULT_KEY_TEMPLATE};
+use crate::storage::multipart::MIN_PART_SIZE;
 use anyhow::{Context, Result};
+use chrono::NaiveTime;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -9,20 +12,233 @@ pub struct Config {
     pub cameras: Vec<CameraConfig>,
     pub recording: RecordingConfig,
     pub upload: UploadConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServiceConfig {
     pub metrics_port: u16,
+    /// Time allowed on SIGTERM to stop FFmpeg and finish uploads; keep it
+    /// below the pod's `terminationGracePeriodSeconds`
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Bearer token for the HTTP routes that change state (bookmarks,
+    /// dead-letter re-drive); they are refused while it is unset
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    25
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct StorageConfig {
+    /// Primary destination, configured directly under `[storage]`
+    #[serde(flatten)]
+    pub primary: DestinationConfig,
+    /// Additional destinations every segment is replicated to
+    #[serde(default)]
+    pub replicas: Vec<DestinationConfig>,
+    /// Object key layout, e.g. `{camera_id}/{date}/{filename}`
+    #[serde(default = "default_key_template")]
+    pub key_template: String,
+    /// When a segment's local copy may be deleted
+    #[serde(default)]
+    pub delete_policy: DeletePolicy,
+    /// Destinations required for `delete_policy = "quorum"` (default: majority)
+    #[serde(default)]
+    pub quorum: Option<usize>,
+    /// What to do when a segment's key already holds different content
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+}
+
+impl StorageConfig {
+    /// Primary destination followed by replicas
+    pub fn destinations(&self) -> impl Iterator<Item = &DestinationConfig> {
+        std::iter::once(&self.primary).chain(&self.replicas)
+    }
+
+    /// Number of destinations that must hold a segment before it counts as uploaded
+    pub fn required_successes(&self) -> usize {
+        let total = 1 + self.replicas.len();
+        match self.delete_policy {
+            DeletePolicy::All => total,
+            DeletePolicy::Quorum => self.quorum.unwrap_or(total / 2 + 1),
+        }
+    }
+}
+
+/// Policy deciding when a replicated segment is safely stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletePolicy {
+    /// Every destination must succeed
+    #[default]
+    All,
+    /// `quorum` destinations must succeed
+    Quorum,
+}
+
+/// How an upload treats a key that already exists with different content
+/// (an identical object always counts as uploaded)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Replace the existing object
+    #[default]
+    Overwrite,
+    /// Store under the key with `-1`, `-2`, ... added to the filename
+    Suffix,
+    /// Fail the upload, leaving the segment for an operator
+    Fail,
+}
+
+/// A single storage destination
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DestinationConfig {
+    /// Unique name used in logs and metrics
+    #[serde(default = "default_destination_name")]
+    pub name: String,
+    #[serde(default)]
+    pub backend: StorageBackendKind,
+    #[serde(default)]
     pub endpoint: String,
+    #[serde(default)]
     pub bucket: String,
+    #[serde(default)]
     pub region: String,
+    #[serde(default)]
     pub access_key_id: String,
+    #[serde(default)]
     pub secret_access_key: String,
+    /// Where S3 credentials come from when they aren't static
+    #[serde(flatten)]
+    pub auth: CredentialsConfig,
+    /// Root directory for the local/NFS backend
+    #[serde(default)]
+    pub local_path: Option<PathBuf>,
+    /// S3 server-side encryption requested on every write
+    #[serde(default)]
+    pub sse: SseMode,
+    /// KMS key for `sse = "sse-kms"`; the bucket's default key when unset
+    #[serde(default)]
+    pub sse_kms_key_id: Option<String>,
+    /// File holding the 32-byte SSE-C key (hex or base64)
+    #[serde(default)]
+    pub sse_customer_key_file: Option<PathBuf>,
+}
+
+/// Source of a destination's S3 credentials
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CredentialSource {
+    /// `access_key_id` / `secret_access_key` from this config
+    #[default]
+    Static,
+    /// Key files (e.g. a mounted Kubernetes secret), re-read as they change
+    File,
+    /// The AWS default chain: env, profile, web identity, ECS/IMDS
+    Default,
+    /// STS AssumeRole on top of the static keys, or the default chain without them
+    AssumeRole,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CredentialsConfig {
+    #[serde(default)]
+    pub credentials: CredentialSource,
+    /// Files holding the keys for `credentials = "file"`
+    #[serde(default)]
+    pub access_key_id_file: Option<PathBuf>,
+    #[serde(default)]
+    pub secret_access_key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub session_token_file: Option<PathBuf>,
+    /// Profile for the default chain (default: `AWS_PROFILE` or "default")
+    #[serde(default)]
+    pub aws_profile: Option<String>,
+    /// Role for `credentials = "assume-role"`
+    #[serde(default)]
+    pub role_arn: Option<String>,
+    #[serde(default)]
+    pub role_session_name: Option<String>,
+    #[serde(default)]
+    pub role_external_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SseMode {
+    #[default]
+    None,
+    /// Bucket-managed keys (AES256)
+    SseS3,
+    /// AWS KMS-managed keys
+    SseKms,
+    /// Customer-provided keys, sent with every request
+    SseC,
+}
+
+impl std::fmt::Display for SseMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::SseS3 => "sse-s3",
+            Self::SseKms => "sse-kms",
+            Self::SseC => "sse-c",
+        })
+    }
+}
+
+impl DestinationConfig {
+    fn validate_credentials(&self) -> Result<()> {
+        let auth = &self.auth;
+        match auth.credentials {
+            CredentialSource::Static => anyhow::ensure!(
+                !self.access_key_id.is_empty() && !self.secret_access_key.is_empty(),
+                "access_key_id and secret_access_key are required for static credentials ({})",
+                self.name
+            ),
+            CredentialSource::File => anyhow::ensure!(
+                auth.access_key_id_file.is_some() && auth.secret_access_key_file.is_some(),
+                "access_key_id_file and secret_access_key_file are required for file credentials ({})",
+                self.name
+            ),
+            CredentialSource::Default => {}
+            CredentialSource::AssumeRole => anyhow::ensure!(
+                auth.role_arn.is_some(),
+                "role_arn is required for assume-role credentials ({})",
+                self.name
+            ),
+        }
+        Ok(())
+    }
+}
+
+fn default_key_template() -> String {
+    DEFAULT_KEY_TEMPLATE.to_string()
+}
+
+fn default_destination_name() -> String {
+    "primary".to_string()
+}
+
+/// Which storage backend uploads go to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackendKind {
+    /// S3-compatible object storage (SeaweedFS)
+    #[default]
+    S3,
+    /// Local or NFS-mounted filesystem
+    Local,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -31,14 +247,116 @@ pub struct CameraConfig {
     pub name: String,
     pub rtsp_url: String,
     pub segment_duration_secs: u64,
+    /// Days to keep this camera's recordings in storage (overrides the retention default)
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+    /// Object key layout for this camera (overrides `storage.key_template`)
+    #[serde(default)]
+    pub key_template: Option<String>,
+    /// Upload bandwidth cap for this camera in kbit/s, on top of the global limit
+    #[serde(default)]
+    pub max_upload_kbps: Option<u64>,
+    /// FFmpeg settings that override `[recording]` for this camera
+    #[serde(flatten)]
+    pub ffmpeg: FfmpegOverrides,
+    /// Reconnect settings that override `[reconnect]` for this camera
+    #[serde(flatten)]
+    pub reconnect: ReconnectOverrides,
+}
+
+/// Per-camera FFmpeg settings; unset fields fall back to `[recording]`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FfmpegOverrides {
+    #[serde(default)]
+    pub rtsp_transport: Option<RtspTransport>,
+    #[serde(default)]
+    pub video_codec: Option<String>,
+    #[serde(default)]
+    pub audio_codec: Option<String>,
+    /// `-vf` filter graph, e.g. "scale=1280:-2" (needs a video codec other than copy)
+    #[serde(default)]
+    pub video_filter: Option<String>,
+    /// `-af` filter graph (needs an audio codec other than copy)
+    #[serde(default)]
+    pub audio_filter: Option<String>,
+    /// Extra arguments placed before `-i`, e.g. ["-timeout", "5000000"]
+    #[serde(default)]
+    pub input_args: Vec<String>,
+    /// Extra arguments placed before the output
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// Per-camera reconnect settings; unset fields fall back to `[reconnect]`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ReconnectOverrides {
+    #[serde(default)]
+    pub initial_backoff_secs: Option<u64>,
+    #[serde(default)]
+    pub max_backoff_secs: Option<u64>,
+    #[serde(default)]
+    pub backoff_jitter: Option<f64>,
+    #[serde(default)]
+    pub healthy_after_secs: Option<u64>,
+    #[serde(default)]
+    pub degraded_after_failures: Option<u32>,
+    #[serde(default)]
+    pub degraded_probe_secs: Option<u64>,
+}
+
+/// How FFmpeg pulls the RTSP stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RtspTransport {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl RtspTransport {
+    /// Value for FFmpeg's `-rtsp_transport`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RecordingConfig {
     pub temp_dir: PathBuf,
+    /// How long uploaded segments are kept locally (0 = delete right after upload)
     pub local_retention_minutes: u64,
     pub video_codec: String,
     pub audio_codec: String,
+    #[serde(default)]
+    pub rtsp_transport: RtspTransport,
+    /// Restart FFmpeg once it has written no frames or bytes for this long
+    /// (0 = never)
+    #[serde(default = "default_stall_timeout_secs")]
+    pub stall_timeout_secs: u64,
+    /// Start evicting local segments when disk usage reaches this percentage
+    #[serde(default = "default_disk_high_watermark_percent")]
+    pub disk_high_watermark_percent: u8,
+    /// Evict until disk usage drops below this percentage
+    #[serde(default = "default_disk_low_watermark_percent")]
+    pub disk_low_watermark_percent: u8,
+    /// Size budget for temp_dir; when unset the filesystem capacity is used
+    #[serde(default)]
+    pub local_capacity_bytes: Option<u64>,
+}
+
+fn default_stall_timeout_secs() -> u64 {
+    30
+}
+
+fn default_disk_high_watermark_percent() -> u8 {
+    90
+}
+
+fn default_disk_low_watermark_percent() -> u8 {
+    75
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -46,6 +364,190 @@ pub struct UploadConfig {
     pub max_concurrent: usize,
     pub max_retries: u32,
     pub retry_backoff_secs: u64,
+    /// Files at least this large are uploaded with resumable multipart uploads
+    #[serde(default = "default_multipart_threshold_bytes")]
+    pub multipart_threshold_bytes: u64,
+    #[serde(default = "default_multipart_part_size_bytes")]
+    pub multipart_part_size_bytes: u64,
+    /// Incomplete multipart uploads older than this are aborted
+    #[serde(default = "default_stale_multipart_hours")]
+    pub stale_multipart_hours: u64,
+    /// Upload bandwidth cap in kbit/s outside any schedule window
+    /// (unset = unlimited, 0 = only upload inside windows)
+    #[serde(default)]
+    pub max_bandwidth_kbps: Option<u64>,
+    /// Time-of-day windows with their own bandwidth cap; the first match wins
+    #[serde(default)]
+    pub schedule: Vec<UploadWindow>,
+    /// Dead-lettered segments are re-driven automatically after this long,
+    /// doubling after each failed re-drive (0 = only re-drive manually)
+    #[serde(default = "default_dead_letter_redrive_minutes")]
+    pub dead_letter_redrive_minutes: u64,
+    /// Dead-lettered segments are deleted locally this long after they failed
+    /// (0 = keep them until disk pressure evicts them)
+    #[serde(default = "default_dead_letter_max_age_hours")]
+    pub dead_letter_max_age_hours: u64,
+    /// The oldest dead-lettered segments are deleted while the dead-letter
+    /// area holds more than this (unset = no cap)
+    #[serde(default)]
+    pub dead_letter_max_bytes: Option<u64>,
+}
+
+/// Local time range (may wrap past midnight) with its own upload bandwidth cap
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UploadWindow {
+    /// e.g. "22:00"
+    pub start: NaiveTime,
+    /// e.g. "06:00"; exclusive
+    pub end: NaiveTime,
+    /// Cap in kbit/s inside the window (unset = unlimited, 0 = paused)
+    #[serde(default)]
+    pub max_bandwidth_kbps: Option<u64>,
+}
+
+fn default_multipart_threshold_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_multipart_part_size_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_stale_multipart_hours() -> u64 {
+    24
+}
+
+fn default_dead_letter_redrive_minutes() -> u64 {
+    60
+}
+
+fn default_dead_letter_max_age_hours() -> u64 {
+    7 * 24
+}
+
+/// Deletion of old recordings from storage destinations
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RetentionConfig {
+    /// Days to keep recordings for cameras without their own `retention_days`.
+    /// When unset, those cameras' recordings are kept forever.
+    #[serde(default)]
+    pub default_days: Option<u32>,
+    /// Log what would be deleted without deleting anything
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default = "default_retention_interval_minutes")]
+    pub interval_minutes: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            default_days: None,
+            dry_run: false,
+            interval_minutes: default_retention_interval_minutes(),
+        }
+    }
+}
+
+fn default_retention_interval_minutes() -> u64 {
+    60
+}
+
+/// How a camera's recorder reconnects after FFmpeg fails.
+///
+/// Waits start at `initial_backoff_secs` and double up to
+/// `max_backoff_secs`. After `degraded_after_failures` sessions in a row
+/// that fail before `healthy_after_secs`, the camera is degraded and only
+/// probed every `degraded_probe_secs` until a session stays up again.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReconnectConfig {
+    #[serde(default = "default_initial_backoff_secs")]
+    pub initial_backoff_secs: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    /// Random spread of each wait, as a fraction of it (0.0 - 1.0)
+    #[serde(default = "default_backoff_jitter")]
+    pub backoff_jitter: f64,
+    /// A session that lasts this long resets the backoff and failure count
+    #[serde(default = "default_healthy_after_secs")]
+    pub healthy_after_secs: u64,
+    /// Fast failures in a row before the camera is degraded (0 = never)
+    #[serde(default = "default_degraded_after_failures")]
+    pub degraded_after_failures: u32,
+    #[serde(default = "default_degraded_probe_secs")]
+    pub degraded_probe_secs: u64,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_secs: default_initial_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            backoff_jitter: default_backoff_jitter(),
+            healthy_after_secs: default_healthy_after_secs(),
+            degraded_after_failures: default_degraded_after_failures(),
+            degraded_probe_secs: default_degraded_probe_secs(),
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// The policy for one camera, with its overrides applied
+    pub fn for_camera(&self, camera: &CameraConfig) -> Self {
+        let overrides = &camera.reconnect;
+        Self {
+            initial_backoff_secs: overrides
+                .initial_backoff_secs
+                .unwrap_or(self.initial_backoff_secs),
+            max_backoff_secs: overrides.max_backoff_secs.unwrap_or(self.max_backoff_secs),
+            backoff_jitter: overrides.backoff_jitter.unwrap_or(self.backoff_jitter),
+            healthy_after_secs: overrides
+                .healthy_after_secs
+                .unwrap_or(self.healthy_after_secs),
+            degraded_after_failures: overrides
+                .degraded_after_failures
+                .unwrap_or(self.degraded_after_failures),
+            degraded_probe_secs: overrides
+                .degraded_probe_secs
+                .unwrap_or(self.degraded_probe_secs),
+        }
+    }
+}
+
+fn default_initial_backoff_secs() -> u64 {
+    1
+}
+
+fn default_max_backoff_secs() -> u64 {
+    60
+}
+
+fn default_backoff_jitter() -> f64 {
+    0.2
+}
+
+fn default_healthy_after_secs() -> u64 {
+    300
+}
+
+fn default_degraded_after_failures() -> u32 {
+    10
+}
+
+fn default_degraded_probe_secs() -> u64 {
+    600
+}
+
+/// Client-side envelope encryption of segments. Master keys never live in
+/// this file; they are read from `key_dir` or `ENCRYPTION_KEY_<ID>` env vars.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EncryptionConfig {
+    /// Key id new segments are encrypted with; encryption is off when unset
+    #[serde(default)]
+    pub active_key_id: Option<String>,
+    /// Directory of `<key_id>.key` files (32 bytes, hex or base64)
+    #[serde(default)]
+    pub key_dir: Option<PathBuf>,
 }
 
 impl Config {
@@ -65,17 +567,13 @@ impl Config {
                 metrics_port: std::env::var("METRICS_PORT")
                     .unwrap_or_else(|_| "9090".to_string())
                     .parse()?,
+                shutdown_timeout_secs: std::env::var("SHUTDOWN_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_shutdown_timeout_secs),
+                admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
             },
-            storage: StorageConfig {
-                endpoint: std::env::var("S3_ENDPOINT").context("S3_ENDPOINT not set")?,
-                bucket: std::env::var("S3_BUCKET")
-                    .unwrap_or_else(|_| "camera-recordings".to_string()),
-                region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
-                access_key_id: std::env::var("S3_ACCESS_KEY_ID")
-                    .context("S3_ACCESS_KEY_ID not set")?,
-                secret_access_key: std::env::var("S3_SECRET_ACCESS_KEY")
-                    .context("S3_SECRET_ACCESS_KEY not set")?,
-            },
+            storage: Self::storage_from_env()?,
             cameras: vec![
                 CameraConfig {
                     id: "camera-1".to_string(),
@@ -83,6 +581,11 @@ impl Config {
                     rtsp_url: std::env::var("CAMERA1_RTSP_URL")
                         .context("CAMERA1_RTSP_URL not set")?,
                     segment_duration_secs: 900, // 15 minutes
+                    retention_days: None,
+                    key_template: None,
+                    max_upload_kbps: None,
+                    ffmpeg: FfmpegOverrides::default(),
+                    reconnect: ReconnectOverrides::default(),
                 },
                 CameraConfig {
                     id: "camera-2".to_string(),
@@ -90,6 +593,11 @@ impl Config {
                     rtsp_url: std::env::var("CAMERA2_RTSP_URL")
                         .context("CAMERA2_RTSP_URL not set")?,
                     segment_duration_secs: 900,
+                    retention_days: None,
+                    key_template: None,
+                    max_upload_kbps: None,
+                    ffmpeg: FfmpegOverrides::default(),
+                    reconnect: ReconnectOverrides::default(),
                 },
             ],
             recording: RecordingConfig {
@@ -97,9 +605,22 @@ impl Config {
                     std::env::var("TEMP_DIR")
                         .unwrap_or_else(|_| "/tmp/camera-recordings".to_string()),
                 ),
-                local_retention_minutes: 60,
+                local_retention_minutes: std::env::var("LOCAL_RETENTION_MINUTES")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(60),
                 video_codec: "copy".to_string(),
                 audio_codec: "aac".to_string(),
+                rtsp_transport: RtspTransport::Tcp,
+                stall_timeout_secs: std::env::var("STALL_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_stall_timeout_secs),
+                disk_high_watermark_percent: default_disk_high_watermark_percent(),
+                disk_low_watermark_percent: default_disk_low_watermark_percent(),
+                local_capacity_bytes: std::env::var("LOCAL_CAPACITY_BYTES")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
             },
             upload: UploadConfig {
                 max_concurrent: std::env::var("MAX_CONCURRENT_UPLOADS")
@@ -108,23 +629,275 @@ impl Config {
                     .unwrap_or(4),
                 max_retries: 5,
                 retry_backoff_secs: 5,
+                multipart_threshold_bytes: default_multipart_threshold_bytes(),
+                multipart_part_size_bytes: std::env::var("MULTIPART_PART_SIZE_BYTES")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_multipart_part_size_bytes),
+                stale_multipart_hours: default_stale_multipart_hours(),
+                max_bandwidth_kbps: std::env::var("UPLOAD_MAX_BANDWIDTH_KBPS")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                schedule: Vec::new(),
+                dead_letter_redrive_minutes: std::env::var("DEAD_LETTER_REDRIVE_MINUTES")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_dead_letter_redrive_minutes),
+                dead_letter_max_age_hours: std::env::var("DEAD_LETTER_MAX_AGE_HOURS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_dead_letter_max_age_hours),
+                dead_letter_max_bytes: std::env::var("DEAD_LETTER_MAX_BYTES")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+            },
+            retention: RetentionConfig {
+                default_days: std::env::var("RETENTION_DAYS")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                dry_run: std::env::var("RETENTION_DRY_RUN")
+                    .map(|v| v == "true" || v == "1")
+                    .unwrap_or(false),
+                interval_minutes: default_retention_interval_minutes(),
+            },
+            reconnect: ReconnectConfig {
+                initial_backoff_secs: std::env::var("RECONNECT_INITIAL_BACKOFF_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_initial_backoff_secs),
+                max_backoff_secs: std::env::var("RECONNECT_MAX_BACKOFF_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_max_backoff_secs),
+                backoff_jitter: std::env::var("RECONNECT_BACKOFF_JITTER")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_backoff_jitter),
+                healthy_after_secs: std::env::var("RECONNECT_HEALTHY_AFTER_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_healthy_after_secs),
+                degraded_after_failures: std::env::var("RECONNECT_DEGRADED_AFTER_FAILURES")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_degraded_after_failures),
+                degraded_probe_secs: std::env::var("RECONNECT_DEGRADED_PROBE_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_degraded_probe_secs),
+            },
+            encryption: EncryptionConfig {
+                active_key_id: std::env::var("ENCRYPTION_ACTIVE_KEY_ID").ok(),
+                key_dir: std::env::var("ENCRYPTION_KEY_DIR").ok().map(PathBuf::from),
             },
         };
         config.validate()?;
         Ok(config)
     }
 
+    fn storage_from_env() -> Result<StorageConfig> {
+        Ok(StorageConfig {
+            primary: Self::destination_from_env()?,
+            key_template: std::env::var("S3_KEY_TEMPLATE")
+                .unwrap_or_else(|_| default_key_template()),
+            replicas: Vec::new(),
+            delete_policy: DeletePolicy::All,
+            quorum: None,
+            conflict_policy: match std::env::var("STORAGE_CONFLICT_POLICY").as_deref() {
+                Ok("overwrite") | Err(_) => ConflictPolicy::Overwrite,
+                Ok("suffix") => ConflictPolicy::Suffix,
+                Ok("fail") => ConflictPolicy::Fail,
+                Ok(other) => anyhow::bail!("Unknown STORAGE_CONFLICT_POLICY: {other}"),
+            },
+        })
+    }
+
+    fn destination_from_env() -> Result<DestinationConfig> {
+        let backend = match std::env::var("STORAGE_BACKEND").as_deref() {
+            Ok("local") => StorageBackendKind::Local,
+            Ok("s3") | Err(_) => StorageBackendKind::S3,
+            Ok(other) => anyhow::bail!("Unknown STORAGE_BACKEND: {other}"),
+        };
+
+        if backend == StorageBackendKind::Local {
+            return Ok(DestinationConfig {
+                name: default_destination_name(),
+                backend,
+                endpoint: String::new(),
+                bucket: String::new(),
+                region: String::new(),
+                access_key_id: String::new(),
+                secret_access_key: String::new(),
+                auth: CredentialsConfig::default(),
+                local_path: Some(PathBuf::from(
+                    std::env::var("STORAGE_LOCAL_PATH").context("STORAGE_LOCAL_PATH not set")?,
+                )),
+                sse: SseMode::None,
+                sse_kms_key_id: None,
+                sse_customer_key_file: None,
+            });
+        }
+
+        Ok(DestinationConfig {
+            name: default_destination_name(),
+            backend,
+            endpoint: std::env::var("S3_ENDPOINT").context("S3_ENDPOINT not set")?,
+            bucket: std::env::var("S3_BUCKET").unwrap_or_else(|_| "camera-recordings".to_string()),
+            region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            access_key_id: std::env::var("S3_ACCESS_KEY_ID").unwrap_or_default(),
+            secret_access_key: std::env::var("S3_SECRET_ACCESS_KEY").unwrap_or_default(),
+            auth: CredentialsConfig {
+                credentials: match std::env::var("S3_CREDENTIALS").as_deref() {
+                    Ok("static") | Err(_) => CredentialSource::Static,
+                    Ok("file") => CredentialSource::File,
+                    Ok("default") => CredentialSource::Default,
+                    Ok("assume-role") => CredentialSource::AssumeRole,
+                    Ok(other) => anyhow::bail!("Unknown S3_CREDENTIALS: {other}"),
+                },
+                access_key_id_file: std::env::var("S3_ACCESS_KEY_ID_FILE")
+                    .ok()
+                    .map(PathBuf::from),
+                secret_access_key_file: std::env::var("S3_SECRET_ACCESS_KEY_FILE")
+                    .ok()
+                    .map(PathBuf::from),
+                session_token_file: std::env::var("S3_SESSION_TOKEN_FILE")
+                    .ok()
+                    .map(PathBuf::from),
+                aws_profile: None,
+                role_arn: std::env::var("S3_ROLE_ARN").ok(),
+                role_session_name: std::env::var("S3_ROLE_SESSION_NAME").ok(),
+                role_external_id: std::env::var("S3_ROLE_EXTERNAL_ID").ok(),
+            },
+            local_path: None,
+            sse: match std::env::var("S3_SSE").as_deref() {
+                Ok("sse-s3") => SseMode::SseS3,
+                Ok("sse-kms") => SseMode::SseKms,
+                Ok("sse-c") => SseMode::SseC,
+                Ok("none") | Err(_) => SseMode::None,
+                Ok(other) => anyhow::bail!("Unknown S3_SSE: {other}"),
+            },
+            sse_kms_key_id: std::env::var("S3_SSE_KMS_KEY_ID").ok(),
+            sse_customer_key_file: std::env::var("S3_SSE_CUSTOMER_KEY_FILE")
+                .ok()
+                .map(PathBuf::from),
+        })
+    }
+
     /// Validate configuration
     fn validate(&self) -> Result<()> {
         anyhow::ensure!(!self.cameras.is_empty(), "No cameras configured");
+        let mut names = std::collections::HashSet::new();
+        for destination in self.storage.destinations() {
+            anyhow::ensure!(
+                names.insert(destination.name.as_str()),
+                "Duplicate storage destination name: {}",
+                destination.name
+            );
+            match destination.backend {
+                StorageBackendKind::S3 => {
+                    anyhow::ensure!(
+                        !destination.endpoint.is_empty(),
+                        "Storage endpoint not configured for {}",
+                        destination.name
+                    );
+                    anyhow::ensure!(
+                        !destination.bucket.is_empty(),
+                        "Storage bucket not configured for {}",
+                        destination.name
+                    );
+                    destination.validate_credentials()?;
+                }
+                StorageBackendKind::Local => {
+                    anyhow::ensure!(
+                        destination.local_path.is_some(),
+                        "Storage local_path not configured for {}",
+                        destination.name
+                    );
+                    anyhow::ensure!(
+                        destination.sse == SseMode::None,
+                        "Server-side encryption is only supported by S3 destinations ({})",
+                        destination.name
+                    );
+                }
+            }
+            anyhow::ensure!(
+                destination.sse != SseMode::SseC || destination.sse_customer_key_file.is_some(),
+                "sse_customer_key_file is required for SSE-C ({})",
+                destination.name
+            );
+        }
+        let required = self.storage.required_successes();
+        anyhow::ensure!(
+            required >= 1 && required <= names.len(),
+            "Storage quorum must be between 1 and the number of destinations"
+        );
         anyhow::ensure!(
-            !self.storage.endpoint.is_empty(),
-            "Storage endpoint not configured"
+            self.recording.disk_low_watermark_percent < self.recording.disk_high_watermark_percent
+                && self.recording.disk_high_watermark_percent <= 100,
+            "Disk watermarks must satisfy low < high <= 100"
         );
         anyhow::ensure!(
-            !self.storage.bucket.is_empty(),
-            "Storage bucket not configured"
+            self.upload.multipart_part_size_bytes >= MIN_PART_SIZE,
+            "Multipart part size must be at least 5 MiB"
         );
+        anyhow::ensure!(
+            self.retention.default_days != Some(0)
+                && self.cameras.iter().all(|c| c.retention_days != Some(0)),
+            "Retention days must be at least 1"
+        );
+        anyhow::ensure!(
+            self.retention.interval_minutes > 0,
+            "Retention interval must be at least 1 minute"
+        );
+        anyhow::ensure!(
+            self.cameras.iter().all(|c| c.max_upload_kbps != Some(0)),
+            "Camera max_upload_kbps must be at least 1"
+        );
+        anyhow::ensure!(
+            self.upload.schedule.iter().all(|w| w.start != w.end),
+            "Upload schedule windows must not start and end at the same time"
+        );
+        for camera in &self.cameras {
+            let reconnect = self.reconnect.for_camera(camera);
+            anyhow::ensure!(
+                reconnect.initial_backoff_secs > 0
+                    && reconnect.initial_backoff_secs <= reconnect.max_backoff_secs,
+                "Camera {} reconnect backoff must satisfy 0 < initial_backoff_secs <= max_backoff_secs",
+                camera.id
+            );
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&reconnect.backoff_jitter),
+                "Camera {} backoff_jitter must be between 0.0 and 1.0",
+                camera.id
+            );
+            anyhow::ensure!(
+                reconnect.degraded_probe_secs > 0,
+                "Camera {} degraded_probe_secs must be at least 1",
+                camera.id
+            );
+
+            // Filters need decoded frames, which stream copy never produces
+            let ffmpeg = &camera.ffmpeg;
+            let video_codec = ffmpeg
+                .video_codec
+                .as_deref()
+                .unwrap_or(&self.recording.video_codec);
+            let audio_codec = ffmpeg
+                .audio_codec
+                .as_deref()
+                .unwrap_or(&self.recording.audio_codec);
+            anyhow::ensure!(
+                ffmpeg.video_filter.is_none() || video_codec != "copy",
+                "Camera {} video_filter needs a video_codec other than copy",
+                camera.id
+            );
+            anyhow::ensure!(
+                ffmpeg.audio_filter.is_none() || audio_codec != "copy",
+                "Camera {} audio_filter needs an audio_codec other than copy",
+                camera.id
+            );
+        }
+        KeyLayout::new(&self.storage, &self.cameras)?;
         Ok(())
     }
 }
@@ -1,15 +1,20 @@
 mod camera;
+mod cli;
 mod config;
 mod health;
 mod metrics;
 mod storage;
 
 use anyhow::{Context, Result};
+use camera::status::{CameraState, CameraStatus};
 use futures::stream::{FuturesUnordered, StreamExt};
 use signal_hook::consts::{SIGINT, SIGTERM};
 use signal_hook_tokio::Signals;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 #[tokio::main]
@@ -38,71 +43,190 @@ async fn main() -> Result<()> {
 
     info!("Loaded configuration for {} cameras", config.cameras.len());
 
+    // Subcommands (e.g. `decrypt`) run once and exit
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&config, &args).await;
+    }
+
     // Create shared state
     let state = Arc::new(RwLock::new(ServiceState {
-        cameras_connected: 0,
         total_cameras: config.cameras.len(),
+        cameras: config
+            .cameras
+            .iter()
+            .map(|camera| (camera.id.clone(), CameraStatus::default()))
+            .collect(),
     }));
 
-    // Initialize S3 client for SeaweedFS
-    let s3_client = storage::S3Client::new(&config.storage).await?;
-    info!("Connected to SeaweedFS at {}", config.storage.endpoint);
+    // Initialize storage destinations (SeaweedFS by default, plus any replicas)
+    let destinations = storage::backend::connect_all(&config.storage, &config.upload).await?;
+    for backend in &destinations {
+        info!(
+            "Using storage destination {}: {}",
+            backend.name(),
+            backend.describe()
+        );
 
-    // Ensure bucket exists
-    s3_client
-        .ensure_bucket_exists()
-        .await
-        .context("Failed to create/verify bucket")?;
+        // Ensure bucket/directory exists
+        backend.ensure_ready().await.with_context(|| {
+            format!(
+                "Failed to create/verify storage destination {}",
+                backend.name()
+            )
+        })?;
+    }
+
+    // Load master keys when client-side encryption is enabled
+    let keyring = match &config.encryption.active_key_id {
+        Some(key_id) => {
+            let keyring = storage::encryption::Keyring::load(&config.encryption)
+                .context("Failed to load encryption keys")?;
+            info!(key_id = %key_id, "Client-side encryption enabled");
+            Some(Arc::new(keyring))
+        }
+        None => None,
+    };
+
+    // Object key layout (validated with the rest of the config)
+    let keys = Arc::new(storage::KeyLayout::new(&config.storage, &config.cameras)?);
+
+    // Periodically abort partial uploads that will never be resumed
+    let stale_upload_age = Duration::from_secs(config.upload.stale_multipart_hours * 3600);
+    let abort_destinations = destinations.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            ticker.tick().await;
+            for backend in &abort_destinations {
+                match backend.abort_stale_uploads(stale_upload_age).await {
+                    Ok(0) => {}
+                    Ok(aborted) => info!(
+                        aborted,
+                        destination = %backend.name(),
+                        "Aborted stale partial uploads"
+                    ),
+                    Err(e) => warn!(
+                        error = %e,
+                        destination = %backend.name(),
+                        "Failed to abort stale partial uploads"
+                    ),
+                }
+            }
+        }
+    });
+
+    // Open the upload journal so queued segments survive restarts
+    let journal = Arc::new(
+        storage::UploadJournal::open(&config.recording.temp_dir)
+            .context("Failed to open upload journal")?,
+    );
 
     // Create upload channel
-    let (upload_tx, upload_rx) = mpsc::channel(1000);
+    let (upload_queue, upload_rx) = storage::UploadQueue::channel(1000, journal.clone());
+
+    // Bandwidth limits and upload windows
+    let limiter = Arc::new(storage::BandwidthLimiter::new(
+        &config.upload,
+        &config.cameras,
+    ));
+    tokio::spawn(limiter.clone().run());
 
     // Start upload worker
     let upload_worker = storage::UploadWorker::new(
         upload_rx,
-        s3_client.clone(),
-        config.upload.max_concurrent,
-        config.upload.max_retries,
-        config.upload.retry_backoff_secs,
+        destinations.clone(),
+        journal.clone(),
+        &config,
+        keys.clone(),
+        keyring,
+        limiter,
     );
 
+    let priorities = upload_worker.priorities();
+    let dead_letters = upload_worker.dead_letters();
+    let manifests = upload_worker.manifests();
+    let drain_uploads = CancellationToken::new();
+    let upload_drain = drain_uploads.clone();
     let upload_handle = tokio::spawn(async move {
-        upload_worker.run().await;
+        upload_worker.run(upload_drain).await;
     });
 
     info!("Upload worker started");
 
+    // Re-drive dead-lettered segments with a long backoff
+    let redrive_base = Duration::from_secs(config.upload.dead_letter_redrive_minutes * 60);
+    tokio::spawn(dead_letters.clone().run(redrive_base));
+
+    // Seal per-day manifests once their day is complete
+    tokio::spawn(manifests.run());
+
+    // Start local retention janitor
+    let janitor = storage::LocalJanitor::new(
+        &config.recording,
+        &config.upload,
+        journal.clone(),
+        dead_letters.clone(),
+    );
+    tokio::spawn(async move {
+        janitor.run().await;
+    });
+
+    // Start remote retention (deletes expired days from storage)
+    let retention = storage::RemoteRetention::new(
+        &config.retention,
+        &config.cameras,
+        destinations.clone(),
+        keys.clone(),
+    );
+    tokio::spawn(async move {
+        retention.run().await;
+    });
+
     // Start metrics server
     let metrics_state = state.clone();
     let metrics_port = config.service.metrics_port;
+    let admin_token = config.service.admin_token.clone();
     tokio::spawn(async move {
-        if let Err(e) = metrics::server::start_server(metrics_port, metrics_state).await {
+        if let Err(e) = metrics::server::start_server(
+            metrics_port,
+            metrics_state,
+            priorities,
+            dead_letters,
+            admin_token,
+        )
+        .await
+        {
             error!("Metrics server failed: {}", e);
         }
     });
 
+    // Anything FFmpeg writes from here on belongs to this process
+    let started_at = std::time::SystemTime::now();
+
     // Start camera recorders
+    let stop_recording = CancellationToken::new();
     let mut recorder_tasks = FuturesUnordered::new();
 
     for camera_config in &config.cameras {
         let camera_cfg = camera_config.clone();
         let recording_cfg = config.recording.clone();
-        let upload_cfg = config.upload.clone();
-        let upload_tx_clone = upload_tx.clone();
+        let reconnect_cfg = config.reconnect.for_camera(camera_config);
+        let upload_queue_clone = upload_queue.clone();
         let state_clone = state.clone();
+        let stop = stop_recording.clone();
 
         // Initialize metrics for this camera
-        metrics::CAMERA_CONNECTED
-            .with_label_values(&[&camera_cfg.id])
-            .set(0.0);
+        CameraState::Starting.publish(&camera_cfg.id);
 
         let task = tokio::spawn(async move {
             if let Err(e) = camera::recorder::run_recorder(
                 camera_cfg,
                 recording_cfg,
-                upload_cfg,
-                upload_tx_clone,
+                reconnect_cfg,
+                upload_queue_clone,
                 state_clone,
+                stop,
             )
             .await
             {
@@ -115,6 +239,33 @@ async fn main() -> Result<()> {
 
     info!("All camera recorders started");
 
+    // Recover segments a previous run left behind in temp_dir
+    let shutdown_journal = journal.clone();
+    let reconcile_config = config.clone();
+    let reconcile_queue = upload_queue.clone();
+    tokio::spawn(async move {
+        match storage::reconcile::reconcile_orphans(
+            &reconcile_config,
+            &destinations,
+            &keys,
+            &journal,
+            &reconcile_queue,
+            started_at,
+        )
+        .await
+        {
+            Ok(summary) => info!(
+                scanned = summary.scanned,
+                requeued = summary.requeued,
+                requeued_mb = summary.requeued_bytes / 1_048_576,
+                already_uploaded = summary.already_uploaded,
+                skipped = summary.skipped,
+                "Temp dir reconciliation complete"
+            ),
+            Err(e) => error!(error = %e, "Temp dir reconciliation failed"),
+        }
+    });
+
     // Handle shutdown signals
     let mut signals = Signals::new([SIGTERM, SIGINT])?;
 
@@ -137,12 +288,38 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Drop upload sender to signal upload worker to finish
-    drop(upload_tx);
+    // Everything below shares one budget, sized to fit the pod's grace period
+    let deadline =
+        tokio::time::Instant::now() + Duration::from_secs(config.service.shutdown_timeout_secs);
 
-    // Wait for upload worker to finish pending uploads
+    // Stop FFmpeg so each camera's last segment is finalized and queued
+    info!("Stopping camera recorders...");
+    stop_recording.cancel();
+    let recorders_stopped = tokio::time::timeout_at(deadline, async {
+        while let Some(result) = recorder_tasks.next().await {
+            if let Err(e) = result {
+                error!("Recorder task panicked: {}", e);
+            }
+        }
+    })
+    .await;
+    if recorders_stopped.is_err() {
+        warn!("Camera recorders did not stop within the shutdown timeout");
+    }
+
+    // Upload what is queued, including the final segments
+    drop(upload_queue);
+    drain_uploads.cancel();
     info!("Waiting for pending uploads to complete...");
-    let _ = tokio::time::timeout(tokio::time::Duration::from_secs(60), upload_handle).await;
+    if tokio::time::timeout_at(deadline, upload_handle)
+        .await
+        .is_err()
+    {
+        warn!(
+            pending = shutdown_journal.pending().len(),
+            "Shutdown timeout reached, remaining uploads resume on next start"
+        );
+    }
 
     info!("Camera recorder service stopped");
     Ok(())
@@ -151,6 +328,28 @@ async fn main() -> Result<()> {
 /// Shared service state
 #[derive(Debug)]
 pub struct ServiceState {
-    pub cameras_connected: usize,
     pub total_cameras: usize,
+    /// Recorder status of each camera, by id
+    pub cameras: BTreeMap<String, CameraStatus>,
+}
+
+impl ServiceState {
+    pub fn cameras_connected(&self) -> usize {
+        self.cameras
+            .values()
+            .filter(|status| status.state == CameraState::Recording)
+            .count()
+    }
+
+    /// Move a camera to `state`, publishing it to the metrics
+    pub fn set_camera_state(&mut self, camera_id: &str, state: CameraState) -> &mut CameraStatus {
+        let status = self.cameras.entry(camera_id.to_string()).or_default();
+        if status.state != state {
+            status.state = state;
+            status.since = chrono::Utc::now();
+        }
+        status.next_attempt_at = None;
+        state.publish(camera_id);
+        status
+    }
 }
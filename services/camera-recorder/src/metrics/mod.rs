@@ -1,7 +1,7 @@
 pub mod server;
 
 use lazy_static::lazy_static;
-use prometheus::{CounterVec, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry};
+use prometheus::{CounterVec, Gauge, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry};
 
 lazy_static! {
     pub static ref REGISTRY: Registry = Registry::new();
@@ -12,6 +12,18 @@ lazy_static! {
         &["camera_id"]
     ).unwrap();
 
+    // Recorder state per camera (1 for the current state, e.g. recording, backoff, degraded)
+    pub static ref CAMERA_STATE: GaugeVec = GaugeVec::new(
+        Opts::new("camera_recorder_state", "Reconnect state of each camera's recorder"),
+        &["camera_id", "state"]
+    ).unwrap();
+
+    // Sessions in a row that failed before becoming healthy
+    pub static ref CAMERA_CONSECUTIVE_FAILURES: GaugeVec = GaugeVec::new(
+        Opts::new("camera_consecutive_failures", "Recording sessions in a row that failed before becoming healthy"),
+        &["camera_id"]
+    ).unwrap();
+
     // Total segments recorded
     pub static ref SEGMENTS_RECORDED: CounterVec = CounterVec::new(
         Opts::new("camera_segments_recorded_total", "Total number of video segments recorded"),
@@ -30,6 +42,25 @@ lazy_static! {
         &["camera_id"]
     ).unwrap();
 
+    // Upload attempts per destination, by result (success, failure)
+    pub static ref DESTINATION_UPLOADS: CounterVec = CounterVec::new(
+        Opts::new("camera_destination_uploads_total", "Upload attempts per storage destination"),
+        &["camera_id", "destination", "result"]
+    ).unwrap();
+
+    // Upload duration per destination, including retries
+    pub static ref DESTINATION_UPLOAD_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("camera_destination_upload_duration_seconds", "Time taken to upload a segment to one destination")
+            .buckets(vec![1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0]),
+        &["destination"]
+    ).unwrap();
+
+    // Uploads whose object didn't match the local file (size or SHA-256)
+    pub static ref CHECKSUM_MISMATCHES: CounterVec = CounterVec::new(
+        Opts::new("camera_upload_checksum_mismatches_total", "Total number of uploads that failed checksum verification"),
+        &["camera_id"]
+    ).unwrap();
+
     // Upload duration
     pub static ref UPLOAD_DURATION: HistogramVec = HistogramVec::new(
         HistogramOpts::new("camera_segment_upload_duration_seconds", "Time taken to upload segments")
@@ -43,6 +74,103 @@ lazy_static! {
         &["camera_id"]
     ).unwrap();
 
+    // FFmpeg sessions killed for writing nothing within the stall timeout
+    pub static ref FFMPEG_STALLS: CounterVec = CounterVec::new(
+        Opts::new("camera_ffmpeg_stalls_total", "Total number of FFmpeg sessions restarted after stalling"),
+        &["camera_id"]
+    ).unwrap();
+
+    // Segments found in temp_dir at startup, by outcome (requeued, already_uploaded)
+    pub static ref ORPHANED_SEGMENTS: CounterVec = CounterVec::new(
+        Opts::new("camera_orphaned_segments_total", "Segments left on disk by a previous run, by reconciliation outcome"),
+        &["camera_id", "outcome"]
+    ).unwrap();
+
+    // Local segments deleted by the janitor (retention, disk_pressure, disk_pressure_not_uploaded)
+    pub static ref SEGMENTS_EVICTED: CounterVec = CounterVec::new(
+        Opts::new("camera_local_segments_evicted_total", "Local segment files deleted by the retention janitor"),
+        &["camera_id", "reason"]
+    ).unwrap();
+
+    // Objects deleted from storage by remote retention
+    pub static ref RETENTION_DELETED_OBJECTS: CounterVec = CounterVec::new(
+        Opts::new("camera_retention_deleted_objects_total", "Objects deleted from storage by retention"),
+        &["camera_id", "destination"]
+    ).unwrap();
+
+    // Bytes deleted from storage by remote retention
+    pub static ref RETENTION_DELETED_BYTES: CounterVec = CounterVec::new(
+        Opts::new("camera_retention_deleted_bytes_total", "Bytes deleted from storage by retention"),
+        &["camera_id", "destination"]
+    ).unwrap();
+
+    // Segments waiting for an upload slot, by priority (important, latest, backlog)
+    pub static ref UPLOAD_QUEUE_DEPTH: GaugeVec = GaugeVec::new(
+        Opts::new("camera_upload_queue_depth", "Segments waiting for upload by priority class"),
+        &["priority"]
+    ).unwrap();
+
+    // Segments the recorder could only journal because the upload channel was full
+    pub static ref UPLOAD_HANDOFF_SPILLS: CounterVec = CounterVec::new(
+        Opts::new("camera_upload_handoff_spills_total", "Segments spilled to the upload journal instead of the upload channel"),
+        &["camera_id"]
+    ).unwrap();
+
+    // Segments recorded but not yet uploaded (queued, uploading or spilled)
+    pub static ref UPLOAD_PENDING_SEGMENTS: Gauge = Gauge::new(
+        "camera_upload_pending_segments", "Segments waiting to be uploaded"
+    ).unwrap();
+
+    // Size of the segments waiting to be uploaded
+    pub static ref UPLOAD_PENDING_BYTES: Gauge = Gauge::new(
+        "camera_upload_pending_bytes", "Bytes waiting to be uploaded"
+    ).unwrap();
+
+    // Age of the oldest segment waiting to be uploaded
+    pub static ref UPLOAD_OLDEST_PENDING_AGE: Gauge = Gauge::new(
+        "camera_upload_oldest_pending_age_seconds", "Age of the oldest segment waiting to be uploaded"
+    ).unwrap();
+
+    // Segments in the dead-letter area after exhausting their upload retries
+    pub static ref DEAD_LETTER_SEGMENTS: GaugeVec = GaugeVec::new(
+        Opts::new("camera_dead_letter_segments", "Segments that failed to upload and await re-drive"),
+        &["camera_id"]
+    ).unwrap();
+
+    // Bytes in the dead-letter area
+    pub static ref DEAD_LETTER_BYTES: GaugeVec = GaugeVec::new(
+        Opts::new("camera_dead_letter_bytes", "Size of segments that failed to upload and await re-drive"),
+        &["camera_id"]
+    ).unwrap();
+
+    // Upload bandwidth limit in bytes/s (scope = global or camera_id, 0 = unlimited)
+    pub static ref UPLOAD_BANDWIDTH_LIMIT: GaugeVec = GaugeVec::new(
+        Opts::new("camera_upload_bandwidth_limit_bytes", "Current upload bandwidth limit"),
+        &["scope"]
+    ).unwrap();
+
+    // Whether uploads are paused outside the configured upload windows
+    pub static ref UPLOADS_PAUSED: Gauge = Gauge::new(
+        "camera_uploads_paused", "Uploads waiting for an upload window (1 = paused)"
+    ).unwrap();
+
+    // Time uploads spent waiting on bandwidth limits or upload windows
+    pub static ref UPLOAD_THROTTLE_WAIT: CounterVec = CounterVec::new(
+        Opts::new("camera_upload_throttle_wait_seconds_total", "Time uploads spent throttled or paused"),
+        &["camera_id"]
+    ).unwrap();
+
+    // Per-day manifest writes per destination, by result (success, failure)
+    pub static ref MANIFEST_WRITES: CounterVec = CounterVec::new(
+        Opts::new("camera_manifest_writes_total", "Manifest object writes per storage destination"),
+        &["destination", "result"]
+    ).unwrap();
+
+    // Local disk usage of temp_dir as a percentage of capacity
+    pub static ref LOCAL_DISK_USAGE: Gauge = Gauge::new(
+        "camera_local_disk_usage_percent", "Disk usage of the recording temp directory"
+    ).unwrap();
+
     // Total bytes recorded
     pub static ref RECORDING_BYTES: CounterVec = CounterVec::new(
         Opts::new("camera_recording_bytes_total", "Total bytes recorded"),
@@ -53,11 +181,33 @@ lazy_static! {
 /// Initialize metrics registry
 pub fn init_metrics() -> Result<(), prometheus::Error> {
     REGISTRY.register(Box::new(CAMERA_CONNECTED.clone()))?;
+    REGISTRY.register(Box::new(CAMERA_STATE.clone()))?;
+    REGISTRY.register(Box::new(CAMERA_CONSECUTIVE_FAILURES.clone()))?;
     REGISTRY.register(Box::new(SEGMENTS_RECORDED.clone()))?;
     REGISTRY.register(Box::new(SEGMENTS_UPLOADED.clone()))?;
     REGISTRY.register(Box::new(UPLOAD_FAILURES.clone()))?;
+    REGISTRY.register(Box::new(CHECKSUM_MISMATCHES.clone()))?;
+    REGISTRY.register(Box::new(DESTINATION_UPLOADS.clone()))?;
+    REGISTRY.register(Box::new(DESTINATION_UPLOAD_DURATION.clone()))?;
     REGISTRY.register(Box::new(UPLOAD_DURATION.clone()))?;
     REGISTRY.register(Box::new(FFMPEG_RESTARTS.clone()))?;
+    REGISTRY.register(Box::new(FFMPEG_STALLS.clone()))?;
     REGISTRY.register(Box::new(RECORDING_BYTES.clone()))?;
+    REGISTRY.register(Box::new(ORPHANED_SEGMENTS.clone()))?;
+    REGISTRY.register(Box::new(SEGMENTS_EVICTED.clone()))?;
+    REGISTRY.register(Box::new(LOCAL_DISK_USAGE.clone()))?;
+    REGISTRY.register(Box::new(RETENTION_DELETED_OBJECTS.clone()))?;
+    REGISTRY.register(Box::new(RETENTION_DELETED_BYTES.clone()))?;
+    REGISTRY.register(Box::new(UPLOAD_QUEUE_DEPTH.clone()))?;
+    REGISTRY.register(Box::new(UPLOAD_HANDOFF_SPILLS.clone()))?;
+    REGISTRY.register(Box::new(UPLOAD_PENDING_SEGMENTS.clone()))?;
+    REGISTRY.register(Box::new(UPLOAD_PENDING_BYTES.clone()))?;
+    REGISTRY.register(Box::new(UPLOAD_OLDEST_PENDING_AGE.clone()))?;
+    REGISTRY.register(Box::new(DEAD_LETTER_SEGMENTS.clone()))?;
+    REGISTRY.register(Box::new(DEAD_LETTER_BYTES.clone()))?;
+    REGISTRY.register(Box::new(UPLOAD_BANDWIDTH_LIMIT.clone()))?;
+    REGISTRY.register(Box::new(UPLOADS_PAUSED.clone()))?;
+    REGISTRY.register(Box::new(UPLOAD_THROTTLE_WAIT.clone()))?;
+    REGISTRY.register(Box::new(MANIFEST_WRITES.clone()))?;
     Ok(())
 }
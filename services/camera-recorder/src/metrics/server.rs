@@ -1,17 +1,72 @@
+use crate::camera::status::CameraStatus;
 use crate::metrics::REGISTRY;
+use crate::storage::dead_letter::DeadLetter;
+use crate::storage::priority::Bookmark;
+use crate::storage::{DeadLetters, UploadPriorities};
 use crate::ServiceState;
 use anyhow::Result;
-use axum::{extract::State as AxumState, routing::get, Router};
+use axum::{
+    extract::{Path, Request, State as AxumState},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
 use prometheus::Encoder;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::info;
 
-pub async fn start_server(port: u16, state: Arc<RwLock<ServiceState>>) -> Result<()> {
+/// Handles shared by the HTTP handlers
+#[derive(Clone)]
+struct AppState {
+    service: Arc<RwLock<ServiceState>>,
+    priorities: UploadPriorities,
+    dead_letters: Arc<DeadLetters>,
+    admin_token: Option<Arc<str>>,
+}
+
+pub async fn start_server(
+    port: u16,
+    state: Arc<RwLock<ServiceState>>,
+    priorities: UploadPriorities,
+    dead_letters: Arc<DeadLetters>,
+    admin_token: Option<String>,
+) -> Result<()> {
+    let state = AppState {
+        service: state,
+        priorities,
+        dead_letters,
+        admin_token: admin_token.map(Arc::from),
+    };
+
+    // The server listens on every interface (hostNetwork), so anything that
+    // changes state needs the admin token
+    let admin = Router::new()
+        .route("/bookmarks", post(bookmark_handler))
+        .route(
+            "/dead-letter/redrive",
+            post(dead_letter_redrive_all_handler),
+        )
+        .route(
+            "/dead-letter/:camera_id/:filename/redrive",
+            post(dead_letter_redrive_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_admin_token,
+        ));
+
     let app = Router::new()
         .route("/health", get(health_handler))
         .route("/ready", get(ready_handler))
+        .route("/status", get(status_handler))
         .route("/metrics", get(metrics_handler))
+        .route("/dead-letter", get(dead_letter_list_handler))
+        .merge(admin)
         .with_state(state);
 
     let addr = format!("0.0.0.0:{port}");
@@ -23,22 +78,127 @@ pub async fn start_server(port: u16, state: Arc<RwLock<ServiceState>>) -> Result
     Ok(())
 }
 
+/// Let a request through only with `Authorization: Bearer <admin_token>`
+async fn require_admin_token(
+    AxumState(state): AxumState<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = &state.admin_token else {
+        return (
+            StatusCode::FORBIDDEN,
+            "No admin token configured (service.admin_token / ADMIN_TOKEN)",
+        )
+            .into_response();
+    };
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if tokens_match(presented, token) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, "Missing or invalid bearer token").into_response(),
+    }
+}
+
+/// Compare without bailing out at the first differing byte
+fn tokens_match(presented: &str, token: &str) -> bool {
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
 async fn health_handler() -> &'static str {
     "OK"
 }
 
-async fn ready_handler(AxumState(state): AxumState<Arc<RwLock<ServiceState>>>) -> String {
-    let s = state.read().await;
-    if s.cameras_connected == s.total_cameras {
+async fn ready_handler(AxumState(state): AxumState<AppState>) -> String {
+    let s = state.service.read().await;
+    let connected = s.cameras_connected();
+    if connected == s.total_cameras {
         "READY".to_string()
     } else {
         format!(
             "NOT_READY: {}/{} cameras connected",
-            s.cameras_connected, s.total_cameras
+            connected, s.total_cameras
         )
     }
 }
 
+#[derive(Serialize)]
+struct ServiceStatus {
+    cameras_connected: usize,
+    total_cameras: usize,
+    cameras: BTreeMap<String, CameraStatus>,
+}
+
+/// Per-camera recorder state, failures and next reconnect attempt
+async fn status_handler(AxumState(state): AxumState<AppState>) -> Json<ServiceStatus> {
+    let s = state.service.read().await;
+    Json(ServiceStatus {
+        cameras_connected: s.cameras_connected(),
+        total_cameras: s.total_cameras,
+        cameras: s.cameras.clone(),
+    })
+}
+
+/// Upload the segment covering a moment ahead of the backlog, e.g. from a
+/// motion detector: `{"camera_id": "camera-1", "reason": "motion"}`
+async fn bookmark_handler(
+    AxumState(state): AxumState<AppState>,
+    Json(bookmark): Json<Bookmark>,
+) -> (StatusCode, String) {
+    if !state.priorities.has_camera(&bookmark.camera_id) {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Unknown camera: {}", bookmark.camera_id),
+        );
+    }
+    let queued = state.priorities.bookmark(bookmark);
+    (
+        StatusCode::ACCEPTED,
+        format!("Bookmarked ({queued} queued segments promoted)"),
+    )
+}
+
+async fn dead_letter_list_handler(
+    AxumState(state): AxumState<AppState>,
+) -> Result<Json<Vec<DeadLetter>>, (StatusCode, String)> {
+    state
+        .dead_letters
+        .list()
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))
+}
+
+async fn dead_letter_redrive_handler(
+    AxumState(state): AxumState<AppState>,
+    Path((camera_id, filename)): Path<(String, String)>,
+) -> (StatusCode, String) {
+    match state
+        .dead_letters
+        .redrive(&format!("{camera_id}/{filename}"))
+        .await
+    {
+        Ok(()) => (StatusCode::ACCEPTED, "Re-driven".to_string()),
+        Err(e) => (StatusCode::NOT_FOUND, format!("{e:#}")),
+    }
+}
+
+async fn dead_letter_redrive_all_handler(
+    AxumState(state): AxumState<AppState>,
+) -> (StatusCode, String) {
+    match state.dead_letters.redrive_all().await {
+        Ok(count) => (StatusCode::ACCEPTED, format!("Re-driven {count} segments")),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")),
+    }
+}
+
 async fn metrics_handler() -> String {
     let encoder = prometheus::TextEncoder::new();
     let metric_families = REGISTRY.gather();
@@ -0,0 +1,95 @@
+use super::metadata::SegmentMetadata;
+use super::throttle::UploadThrottle;
+use super::{LocalBackend, S3Client};
+use crate::config::{DestinationConfig, StorageBackendKind, StorageConfig, UploadConfig};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// An object as seen by a storage backend
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    /// User metadata; only populated by `head`, listings leave it empty
+    pub metadata: HashMap<String, String>,
+}
+
+/// Where uploaded segments end up
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Destination name from config, used as a metrics label
+    fn name(&self) -> &str;
+
+    /// Short description for logs, e.g. the endpoint and bucket
+    fn describe(&self) -> String;
+
+    /// Make sure the destination exists and is writable
+    async fn ensure_ready(&self) -> Result<()>;
+
+    /// Store a local file under `key`.
+    ///
+    /// Implementations verify the stored object against the local file
+    /// before returning `Ok`, so the caller may delete the local copy.
+    /// Implementations read the file through `throttle` so uploads respect
+    /// the configured bandwidth limits.
+    async fn put(
+        &self,
+        local_path: &Path,
+        key: &str,
+        metadata: &SegmentMetadata,
+        throttle: &UploadThrottle,
+    ) -> Result<()>;
+
+    /// Replace a small object (e.g. a manifest) in one request, so readers
+    /// see either the old or the new contents
+    async fn put_bytes(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()>;
+
+    /// Read a whole (small) object, `None` if it doesn't exist
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Look up a single object, `None` if it doesn't exist
+    async fn head(&self, key: &str) -> Result<Option<ObjectInfo>>;
+
+    /// All objects whose key starts with `prefix`
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>>;
+
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Read a byte range of an object
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<Vec<u8>>;
+
+    /// Clean up partial uploads older than `max_age`, returning how many were removed
+    async fn abort_stale_uploads(&self, _max_age: Duration) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+/// Build the backend selected for a destination
+pub async fn connect(
+    config: &DestinationConfig,
+    upload: &UploadConfig,
+) -> Result<Arc<dyn StorageBackend>> {
+    Ok(match config.backend {
+        StorageBackendKind::S3 => Arc::new(S3Client::new(config, upload).await?),
+        StorageBackendKind::Local => Arc::new(LocalBackend::new(config)?),
+    })
+}
+
+/// Build backends for the primary destination and every replica
+pub async fn connect_all(
+    config: &StorageConfig,
+    upload: &UploadConfig,
+) -> Result<Vec<Arc<dyn StorageBackend>>> {
+    let mut backends = Vec::new();
+    for destination in config.destinations() {
+        backends.push(connect(destination, upload).await?);
+    }
+    Ok(backends)
+}
@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+/// Object metadata key holding the hex SHA-256 of the uploaded bytes
+pub const SHA256_METADATA_KEY: &str = "sha256";
+
+/// The stored object doesn't match the local file it was uploaded from
+#[derive(Debug, thiserror::Error)]
+#[error("Stored object {key} does not match local file: {detail}")]
+pub struct ChecksumMismatch {
+    pub key: String,
+    pub detail: String,
+}
+
+/// The key already holds different content and the conflict policy is `fail`
+#[derive(Debug, thiserror::Error)]
+#[error("Object {key} already exists with different content: {detail}")]
+pub struct KeyConflict {
+    pub key: String,
+    pub detail: String,
+}
+
+/// SHA-256 digest of a file's contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sha256Digest([u8; 32]);
+
+impl Sha256Digest {
+    /// Hash a file in a blocking task, streaming it through the hasher
+    pub async fn of_file(path: &Path) -> Result<Self> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let mut file = std::fs::File::open(&path).context("Failed to open file for hashing")?;
+            let mut hasher = Sha256::new();
+            let mut buf = vec![0u8; 1024 * 1024];
+            loop {
+                let n = file
+                    .read(&mut buf)
+                    .context("Failed to read file for hashing")?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            Ok(Self(hasher.finalize().into()))
+        })
+        .await?
+    }
+
+    /// Lowercase hex, as stored in object metadata
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// Base64, as used by the `x-amz-checksum-sha256` header
+    pub fn to_base64(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD.encode(self.0)
+    }
+}
@@ -0,0 +1,167 @@
+use crate::config::{CredentialSource, DestinationConfig};
+use anyhow::{Context, Result};
+use aws_config::default_provider::credentials::DefaultCredentialsChain;
+use aws_config::sts::AssumeRoleProvider;
+use aws_credential_types::provider::error::CredentialsError;
+use aws_credential_types::provider::{future, ProvideCredentials};
+use aws_credential_types::Credentials;
+use aws_sdk_s3::config::{Region, SharedCredentialsProvider};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tracing::info;
+
+/// File credentials are handed out with this lifetime, so the SDK re-reads
+/// the files about this often and picks up rotated keys without a restart
+const FILE_CREDENTIALS_TTL: Duration = Duration::from_secs(60);
+
+/// Build the credentials provider configured for an S3 destination.
+///
+/// Every provider except `static` refreshes itself: the SDK caches what it
+/// returns until shortly before it expires and then asks again.
+pub async fn provider(config: &DestinationConfig) -> Result<SharedCredentialsProvider> {
+    let auth = &config.auth;
+    let region = Region::new(config.region.clone());
+    let provider = match auth.credentials {
+        CredentialSource::Static => SharedCredentialsProvider::new(static_credentials(config)),
+        CredentialSource::File => {
+            let provider = FileCredentials {
+                access_key_id_file: required(&auth.access_key_id_file, "access_key_id_file")?,
+                secret_access_key_file: required(
+                    &auth.secret_access_key_file,
+                    "secret_access_key_file",
+                )?,
+                session_token_file: auth.session_token_file.clone(),
+                last_key_id: Mutex::new(None),
+            };
+            // Fail at startup rather than on the first upload
+            provider.load()?;
+            SharedCredentialsProvider::new(provider)
+        }
+        CredentialSource::Default => {
+            SharedCredentialsProvider::new(default_chain(config, region).await)
+        }
+        CredentialSource::AssumeRole => {
+            let role_arn = auth
+                .role_arn
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("role_arn is required for assume-role"))?;
+            let mut builder = AssumeRoleProvider::builder(role_arn)
+                .session_name(
+                    auth.role_session_name
+                        .clone()
+                        .unwrap_or_else(|| format!("camera-recorder-{}", config.name)),
+                )
+                .region(region.clone());
+            if let Some(external_id) = &auth.role_external_id {
+                builder = builder.external_id(external_id);
+            }
+            // Static keys, if given, are the identity that assumes the role
+            let assume_role = if config.access_key_id.is_empty() {
+                builder
+                    .build_from_provider(default_chain(config, region).await)
+                    .await
+            } else {
+                builder
+                    .build_from_provider(static_credentials(config))
+                    .await
+            };
+            SharedCredentialsProvider::new(assume_role)
+        }
+    };
+
+    info!(
+        destination = %config.name,
+        credentials = ?auth.credentials,
+        "S3 credentials provider configured"
+    );
+    Ok(provider)
+}
+
+fn static_credentials(config: &DestinationConfig) -> Credentials {
+    Credentials::new(
+        &config.access_key_id,
+        &config.secret_access_key,
+        None,
+        None,
+        "static",
+    )
+}
+
+async fn default_chain(config: &DestinationConfig, region: Region) -> DefaultCredentialsChain {
+    let mut builder = DefaultCredentialsChain::builder().region(region);
+    if let Some(profile) = &config.auth.aws_profile {
+        builder = builder.profile_name(profile);
+    }
+    builder.build().await
+}
+
+fn required(path: &Option<PathBuf>, field: &str) -> Result<PathBuf> {
+    path.clone()
+        .ok_or_else(|| anyhow::anyhow!("{field} is required for file credentials"))
+}
+
+/// Credentials read from files, e.g. a mounted Kubernetes secret.
+///
+/// The files are read again every time the SDK refreshes, so rotating the
+/// secret takes effect within [`FILE_CREDENTIALS_TTL`].
+#[derive(Debug)]
+struct FileCredentials {
+    access_key_id_file: PathBuf,
+    secret_access_key_file: PathBuf,
+    session_token_file: Option<PathBuf>,
+    /// Access key last handed out, to log rotations
+    last_key_id: Mutex<Option<String>>,
+}
+
+impl FileCredentials {
+    fn load(&self) -> Result<Credentials> {
+        let access_key_id = read_secret(&self.access_key_id_file)?;
+        let secret_access_key = read_secret(&self.secret_access_key_file)?;
+        let session_token = self
+            .session_token_file
+            .as_deref()
+            .map(read_secret)
+            .transpose()?;
+
+        let mut last_key_id = self.last_key_id.lock().unwrap();
+        if last_key_id.as_deref() != Some(access_key_id.as_str()) {
+            if last_key_id.is_some() {
+                info!(
+                    path = %self.access_key_id_file.display(),
+                    "S3 credentials rotated"
+                );
+            }
+            *last_key_id = Some(access_key_id.clone());
+        }
+
+        Ok(Credentials::new(
+            access_key_id,
+            secret_access_key,
+            session_token,
+            Some(SystemTime::now() + FILE_CREDENTIALS_TTL),
+            "file",
+        ))
+    }
+}
+
+impl ProvideCredentials for FileCredentials {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::ready(
+            self.load()
+                .map_err(|e| CredentialsError::provider_error(format!("{e:#}"))),
+        )
+    }
+}
+
+/// Read a secret file, ignoring surrounding whitespace
+fn read_secret(path: &Path) -> Result<String> {
+    let value = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let value = value.trim();
+    anyhow::ensure!(!value.is_empty(), "{} is empty", path.display());
+    Ok(value.to_string())
+}
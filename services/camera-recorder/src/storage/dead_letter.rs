@@ -0,0 +1,300 @@
+use super::priority::UploadPriorities;
+use super::{encryption, multipart, SegmentInfo, UploadJournal};
+use crate::config::CameraConfig;
+use crate::metrics;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::{interval, Duration};
+use tracing::{info, warn};
+
+/// Directory under the recording temp dir holding segments that failed to upload
+const DEAD_LETTER_DIR: &str = "dead-letter";
+/// Each dead-lettered segment has a `<filename>.json` record next to it
+const RECORD_SUFFIX: &str = ".json";
+/// Automatic re-drives back off up to this long between attempts
+const MAX_REDRIVE_BACKOFF: Duration = Duration::from_secs(24 * 3600);
+/// How often the background re-drive looks for segments that are due
+const REDRIVE_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// A segment that exhausted its upload retries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Where the segment was recorded (and returns to when re-driven)
+    #[serde(flatten)]
+    pub segment: SegmentInfo,
+    /// Last upload error
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+    pub size_bytes: u64,
+    /// Destinations that already hold a verified copy
+    #[serde(default)]
+    pub uploaded_to: BTreeSet<String>,
+    /// Times the segment was re-driven before failing again
+    #[serde(default)]
+    pub redrives: u32,
+}
+
+impl DeadLetter {
+    /// `<camera_id>/<filename>`, used to address one dead letter
+    pub fn id(&self) -> String {
+        let filename = self
+            .segment
+            .local_path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or("unknown");
+        format!("{}/{filename}", self.segment.camera_id)
+    }
+
+    /// When the background re-drive will next try this segment
+    pub fn next_redrive(&self, base: Duration) -> DateTime<Utc> {
+        let backoff = base
+            .saturating_mul(2u32.saturating_pow(self.redrives))
+            .min(MAX_REDRIVE_BACKOFF);
+        self.failed_at + chrono::Duration::from_std(backoff).unwrap_or(chrono::Duration::MAX)
+    }
+}
+
+/// Dead-letter area for segments that failed permanently.
+///
+/// Failed segments are moved out of the camera's temp directory so nothing
+/// retries them behind the operator's back, with the last error recorded
+/// next to them. Re-driving moves a segment back and queues it again.
+pub struct DeadLetters {
+    dir: PathBuf,
+    journal: Arc<UploadJournal>,
+    queue: UploadPriorities,
+    /// Cameras whose gauges are reported even when they have no dead letters
+    camera_ids: Vec<String>,
+}
+
+impl DeadLetters {
+    pub fn new(
+        temp_dir: &Path,
+        cameras: &[CameraConfig],
+        journal: Arc<UploadJournal>,
+        queue: UploadPriorities,
+    ) -> Self {
+        Self {
+            dir: temp_dir.join(DEAD_LETTER_DIR),
+            journal,
+            queue,
+            camera_ids: cameras.iter().map(|c| c.id.clone()).collect(),
+        }
+    }
+
+    fn paths(&self, id: &str) -> Result<(PathBuf, PathBuf)> {
+        let (camera_id, filename) = id
+            .split_once('/')
+            .filter(|(c, f)| !c.is_empty() && !f.is_empty() && !f.contains('/'))
+            .with_context(|| format!("Invalid dead letter id: {id}"))?;
+        anyhow::ensure!(
+            !camera_id.starts_with('.') && !filename.starts_with('.'),
+            "Invalid dead letter id: {id}"
+        );
+        let path = self.dir.join(camera_id).join(filename);
+        let record = PathBuf::from(format!("{}{RECORD_SUFFIX}", path.display()));
+        Ok((path, record))
+    }
+
+    /// Move a failed segment into the dead-letter area
+    pub async fn add(&self, segment: &SegmentInfo, error: &str) -> Result<()> {
+        let entry = self.journal.entry(&segment.local_path);
+        let dead_letter = DeadLetter {
+            segment: segment.clone(),
+            error: error.to_string(),
+            failed_at: Utc::now(),
+            size_bytes: tokio::fs::metadata(&segment.local_path)
+                .await
+                .context("Failed to stat failed segment")?
+                .len(),
+            uploaded_to: entry
+                .as_ref()
+                .map(|e| e.uploaded_to.clone())
+                .unwrap_or_default(),
+            redrives: entry.map_or(0, |e| e.redrives),
+        };
+        let (path, record) = self.paths(&dead_letter.id())?;
+        tokio::fs::create_dir_all(path.parent().unwrap_or(&self.dir)).await?;
+
+        // The record goes first, so a file in here always has its error
+        tokio::fs::write(&record, serde_json::to_vec_pretty(&dead_letter)?)
+            .await
+            .context("Failed to write dead letter record")?;
+        tokio::fs::rename(&segment.local_path, &path)
+            .await
+            .context("Failed to move segment to dead-letter area")?;
+
+        // Partial uploads restart from scratch when re-driven
+        if let Err(e) = multipart::remove_all_state(&segment.local_path).await {
+            warn!(error = %e, "Failed to remove multipart state");
+        }
+        if let Err(e) = encryption::remove_encrypted_copy(&segment.local_path).await {
+            warn!(error = %e, "Failed to remove encrypted copy");
+        }
+        self.journal.forget(&segment.local_path);
+
+        warn!(
+            camera_id = %segment.camera_id,
+            id = %dead_letter.id(),
+            error = %error,
+            "Segment moved to dead-letter area"
+        );
+        self.update_metrics().await;
+        Ok(())
+    }
+
+    /// Every dead-lettered segment, oldest failure first
+    pub async fn list(&self) -> Result<Vec<DeadLetter>> {
+        let mut dead_letters = Vec::new();
+        let mut cameras = match tokio::fs::read_dir(&self.dir).await {
+            Ok(cameras) => cameras,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(dead_letters),
+            Err(e) => return Err(e).context("Failed to read dead-letter area"),
+        };
+        while let Some(camera) = cameras.next_entry().await? {
+            if !camera.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut files = tokio::fs::read_dir(camera.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let path = file.path();
+                if path.to_str().is_some_and(|p| p.ends_with(RECORD_SUFFIX)) {
+                    continue;
+                }
+                let record = PathBuf::from(format!("{}{RECORD_SUFFIX}", path.display()));
+                match tokio::fs::read(&record).await.map(|data| {
+                    serde_json::from_slice::<DeadLetter>(&data).map_err(std::io::Error::from)
+                }) {
+                    Ok(Ok(dead_letter)) => dead_letters.push(dead_letter),
+                    Ok(Err(e)) | Err(e) => {
+                        warn!(error = %e, path = %path.display(), "Unreadable dead letter record");
+                    }
+                }
+            }
+        }
+        dead_letters.sort_by_key(|d| d.failed_at);
+        Ok(dead_letters)
+    }
+
+    /// Move one segment back and queue it for upload
+    pub async fn redrive(&self, id: &str) -> Result<()> {
+        let (path, record) = self.paths(id)?;
+        let data = tokio::fs::read(&record)
+            .await
+            .with_context(|| format!("No dead letter {id}"))?;
+        let dead_letter: DeadLetter =
+            serde_json::from_slice(&data).context("Failed to parse dead letter record")?;
+        let segment = dead_letter.segment;
+
+        if let Some(parent) = segment.local_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(&path, &segment.local_path)
+            .await
+            .context("Failed to move segment out of the dead-letter area")?;
+        self.journal
+            .record_redrive(&segment, dead_letter.uploaded_to, dead_letter.redrives + 1)?;
+        tokio::fs::remove_file(&record).await?;
+
+        info!(
+            camera_id = %segment.camera_id,
+            id = %id,
+            redrives = dead_letter.redrives + 1,
+            "Re-driving dead-lettered segment"
+        );
+        self.queue.push(segment);
+        self.update_metrics().await;
+        Ok(())
+    }
+
+    /// Delete a dead-lettered segment and its record, returning the bytes freed
+    pub async fn remove(&self, dead_letter: &DeadLetter) -> Result<u64> {
+        let (path, record) = self.paths(&dead_letter.id())?;
+        // The segment goes first, so a file in here always has its error
+        for file in [&path, &record] {
+            match tokio::fs::remove_file(file).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).context("Failed to delete dead-lettered segment"),
+            }
+        }
+        self.update_metrics().await;
+        Ok(dead_letter.size_bytes)
+    }
+
+    /// Re-drive every dead-lettered segment, returning how many were queued
+    pub async fn redrive_all(&self) -> Result<usize> {
+        let mut count = 0;
+        for dead_letter in self.list().await? {
+            self.redrive(&dead_letter.id()).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Re-drive segments in the background, backing off exponentially from
+    /// `base` after each failed attempt
+    pub async fn run(self: Arc<Self>, base: Duration) {
+        self.update_metrics().await;
+        if base.is_zero() {
+            info!("Automatic dead-letter re-drive disabled");
+            return;
+        }
+
+        let mut ticker = interval(REDRIVE_CHECK_INTERVAL.min(base));
+        loop {
+            ticker.tick().await;
+
+            let dead_letters = match self.list().await {
+                Ok(dead_letters) => dead_letters,
+                Err(e) => {
+                    warn!(error = %e, "Failed to list dead-lettered segments");
+                    continue;
+                }
+            };
+            let now = Utc::now();
+            for dead_letter in dead_letters {
+                if dead_letter.next_redrive(base) > now {
+                    continue;
+                }
+                if let Err(e) = self.redrive(&dead_letter.id()).await {
+                    warn!(error = %e, id = %dead_letter.id(), "Automatic re-drive failed");
+                }
+            }
+        }
+    }
+
+    /// Refresh the dead-letter gauges
+    async fn update_metrics(&self) {
+        let dead_letters = match self.list().await {
+            Ok(dead_letters) => dead_letters,
+            Err(e) => {
+                warn!(error = %e, "Failed to list dead-lettered segments");
+                return;
+            }
+        };
+        let mut totals: HashMap<&str, (usize, u64)> = self
+            .camera_ids
+            .iter()
+            .map(|id| (id.as_str(), (0, 0)))
+            .collect();
+        for dead_letter in &dead_letters {
+            let total = totals.entry(&dead_letter.segment.camera_id).or_default();
+            total.0 += 1;
+            total.1 += dead_letter.size_bytes;
+        }
+        for (camera_id, (count, bytes)) in totals {
+            metrics::DEAD_LETTER_SEGMENTS
+                .with_label_values(&[camera_id])
+                .set(count as f64);
+            metrics::DEAD_LETTER_BYTES
+                .with_label_values(&[camera_id])
+                .set(bytes as f64);
+        }
+    }
+}
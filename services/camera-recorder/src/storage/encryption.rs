@@ -0,0 +1,383 @@
+use super::StorageBackend;
+use crate::config::EncryptionConfig;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Value of the `encryption` metadata key on encrypted objects
+pub const ALGORITHM: &str = "aes-256-gcm-stream";
+pub const ALGORITHM_METADATA_KEY: &str = "encryption";
+pub const KEY_ID_METADATA_KEY: &str = "encryption-key-id";
+
+/// Master keys can be passed as `ENCRYPTION_KEY_<ID>` (e.g. `ENCRYPTION_KEY_2025_01`
+/// for key id `2025-01`)
+const KEY_ENV_PREFIX: &str = "ENCRYPTION_KEY_";
+const KEY_FILE_EXTENSION: &str = "key";
+const ENCRYPTED_SUFFIX: &str = ".enc";
+
+const MAGIC: &[u8; 8] = b"CAMENC01";
+/// Plaintext bytes per GCM chunk
+const CHUNK_SIZE: u32 = 1024 * 1024;
+const TAG_SIZE: u64 = 16;
+const STREAM_NONCE_SIZE: usize = 7;
+const WRAP_NONCE_SIZE: usize = 12;
+const WRAPPED_KEY_SIZE: usize = 32 + TAG_SIZE as usize;
+/// Upper bound on the header, used when reading it from storage
+const MAX_HEADER_SIZE: u64 =
+    8 + 1 + 255 + (WRAP_NONCE_SIZE + WRAPPED_KEY_SIZE + STREAM_NONCE_SIZE) as u64 + 4;
+
+/// Master keys available to this process, by key id
+pub struct Keyring {
+    active: Option<String>,
+    keys: HashMap<String, Key<Aes256Gcm>>,
+}
+
+impl Keyring {
+    /// Load master keys from `key_dir` (`<key_id>.key` files) and the
+    /// environment. Old keys stay loadable after rotation so existing
+    /// segments can still be decrypted.
+    pub fn load(config: &EncryptionConfig) -> Result<Self> {
+        let mut keys = HashMap::new();
+
+        if let Some(dir) = &config.key_dir {
+            let entries = std::fs::read_dir(dir)
+                .with_context(|| format!("Failed to read key directory {}", dir.display()))?;
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some(KEY_FILE_EXTENSION) {
+                    continue;
+                }
+                let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read key file {}", path.display()))?;
+                keys.insert(
+                    id.to_string(),
+                    parse_key(&text)
+                        .with_context(|| format!("Invalid key file {}", path.display()))?,
+                );
+            }
+        }
+
+        for (name, value) in std::env::vars() {
+            if let Some(id) = name.strip_prefix(KEY_ENV_PREFIX) {
+                let id = id.to_lowercase().replace('_', "-");
+                keys.insert(
+                    id,
+                    parse_key(&value).with_context(|| format!("Invalid key in {name}"))?,
+                );
+            }
+        }
+
+        for id in keys.keys() {
+            anyhow::ensure!(
+                !id.is_empty() && id.len() <= 255,
+                "Encryption key ids must be 1-255 bytes: {id}"
+            );
+        }
+        if let Some(active) = &config.active_key_id {
+            anyhow::ensure!(
+                keys.contains_key(active),
+                "Active encryption key {active} not found in key_dir or {KEY_ENV_PREFIX}* env vars"
+            );
+        }
+
+        Ok(Self {
+            active: config.active_key_id.clone(),
+            keys,
+        })
+    }
+
+    fn key(&self, id: &str) -> Result<&Key<Aes256Gcm>> {
+        self.keys
+            .get(id)
+            .with_context(|| format!("Encryption key {id} is not available"))
+    }
+}
+
+fn parse_key(text: &str) -> Result<Key<Aes256Gcm>> {
+    Ok(decode_key(text)?.into())
+}
+
+/// 32-byte key as hex or base64
+pub(crate) fn decode_key(text: &str) -> Result<[u8; 32]> {
+    let text = text.trim();
+    let bytes = if text.len() == 64 {
+        hex::decode(text)?
+    } else {
+        base64::engine::general_purpose::STANDARD.decode(text)?
+    };
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Encryption keys must be 32 bytes"))
+}
+
+/// Start of every encrypted segment; the data key is stored wrapped by the
+/// master key so the file can be decrypted without its object metadata
+struct Header {
+    key_id: String,
+    wrap_nonce: [u8; WRAP_NONCE_SIZE],
+    wrapped_key: Vec<u8>,
+    stream_nonce: [u8; STREAM_NONCE_SIZE],
+    chunk_size: u32,
+}
+
+impl Header {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAX_HEADER_SIZE as usize);
+        out.extend_from_slice(MAGIC);
+        out.push(self.key_id.len() as u8);
+        out.extend_from_slice(self.key_id.as_bytes());
+        out.extend_from_slice(&self.wrap_nonce);
+        out.extend_from_slice(&self.wrapped_key);
+        out.extend_from_slice(&self.stream_nonce);
+        out.extend_from_slice(&self.chunk_size.to_be_bytes());
+        out
+    }
+
+    /// Parse a header, returning it and its length
+    fn decode(bytes: &[u8]) -> Result<(Self, u64)> {
+        let mut rest = bytes;
+        let mut take = |n: usize| -> Result<&[u8]> {
+            anyhow::ensure!(rest.len() >= n, "Truncated encryption header");
+            let (head, tail) = rest.split_at(n);
+            rest = tail;
+            Ok(head)
+        };
+
+        anyhow::ensure!(take(MAGIC.len())? == MAGIC, "Not an encrypted segment");
+        let id_len = take(1)?[0] as usize;
+        let key_id = String::from_utf8(take(id_len)?.to_vec()).context("Invalid key id")?;
+        let wrap_nonce = take(WRAP_NONCE_SIZE)?.try_into()?;
+        let wrapped_key = take(WRAPPED_KEY_SIZE)?.to_vec();
+        let stream_nonce = take(STREAM_NONCE_SIZE)?.try_into()?;
+        let chunk_size = u32::from_be_bytes(take(4)?.try_into()?);
+        anyhow::ensure!(chunk_size > 0, "Invalid chunk size in encryption header");
+
+        let header = Self {
+            key_id,
+            wrap_nonce,
+            wrapped_key,
+            stream_nonce,
+            chunk_size,
+        };
+        let len = (bytes.len() - rest.len()) as u64;
+        Ok((header, len))
+    }
+
+    /// Unwrap the data key and set up chunk decryption
+    fn decryptor(&self, keyring: &Keyring) -> Result<DecryptorBE32<Aes256Gcm>> {
+        let master = Aes256Gcm::new(keyring.key(&self.key_id)?);
+        let data_key = master
+            .decrypt(
+                Nonce::from_slice(&self.wrap_nonce),
+                Payload {
+                    msg: &self.wrapped_key,
+                    aad: self.key_id.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to unwrap data key with key {}", self.key_id))?;
+        Ok(DecryptorBE32::from_aead(
+            Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key)),
+            self.stream_nonce.as_slice().into(),
+        ))
+    }
+
+    /// Ciphertext byte ranges of each chunk, for a file of `total` bytes
+    fn chunk_ranges(&self, header_len: u64, total: u64) -> Vec<std::ops::Range<u64>> {
+        let chunk_len = u64::from(self.chunk_size) + TAG_SIZE;
+        let mut ranges = Vec::new();
+        let mut start = header_len;
+        loop {
+            let end = (start + chunk_len).min(total);
+            ranges.push(start..end);
+            if end >= total {
+                return ranges;
+            }
+            start = end;
+        }
+    }
+}
+
+/// Where the encrypted copy of a segment is staged for upload
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(ENCRYPTED_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Remove a segment's staged encrypted copy, ignoring a missing file
+pub async fn remove_encrypted_copy(path: &Path) -> Result<()> {
+    match tokio::fs::remove_file(encrypted_path(path)).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).context("Failed to remove encrypted copy"),
+    }
+}
+
+/// Encrypt a segment with the active key, returning the encrypted copy and
+/// the key id used.
+///
+/// A copy left by an earlier attempt is reused, so every retry (and every
+/// destination) uploads the same bytes and multipart uploads can resume.
+pub async fn encrypt_segment(keyring: &Keyring, path: &Path) -> Result<(PathBuf, String)> {
+    let output = encrypted_path(path);
+    if let Ok(mut file) = File::open(&output) {
+        let mut buf = Vec::new();
+        (&mut file).take(MAX_HEADER_SIZE).read_to_end(&mut buf)?;
+        if let Ok((header, _)) = Header::decode(&buf) {
+            if keyring.keys.contains_key(&header.key_id) {
+                return Ok((output, header.key_id));
+            }
+        }
+    }
+
+    let key_id = keyring
+        .active
+        .clone()
+        .context("No active encryption key configured")?;
+    let master = *keyring.key(&key_id)?;
+    let input = path.to_path_buf();
+    let result_path = output.clone();
+    let result_key_id = key_id.clone();
+    tokio::task::spawn_blocking(move || encrypt_file(&master, &key_id, &input, &output)).await??;
+    Ok((result_path, result_key_id))
+}
+
+fn encrypt_file(master: &Key<Aes256Gcm>, key_id: &str, input: &Path, output: &Path) -> Result<()> {
+    let data_key = Aes256Gcm::generate_key(&mut OsRng);
+    let wrap_nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let wrapped_key = Aes256Gcm::new(master)
+        .encrypt(
+            &wrap_nonce,
+            Payload {
+                msg: &data_key,
+                aad: key_id.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to wrap data key"))?;
+    let mut stream_nonce = [0u8; STREAM_NONCE_SIZE];
+    OsRng.fill_bytes(&mut stream_nonce);
+
+    let header = Header {
+        key_id: key_id.to_string(),
+        wrap_nonce: wrap_nonce.into(),
+        wrapped_key,
+        stream_nonce,
+        chunk_size: CHUNK_SIZE,
+    };
+
+    let mut reader = File::open(input).context("Failed to open segment")?;
+    let size = reader.metadata()?.len();
+    let tmp_path = output.with_extension("enc.tmp");
+    let mut writer = File::create(&tmp_path).context("Failed to create encrypted copy")?;
+    writer.write_all(&header.encode())?;
+
+    let mut encryptor = Some(EncryptorBE32::from_aead(
+        Aes256Gcm::new(&data_key),
+        stream_nonce.as_slice().into(),
+    ));
+    let chunk_size = u64::from(CHUNK_SIZE);
+    let chunks = size.div_ceil(chunk_size).max(1);
+    let mut buf = vec![0u8; CHUNK_SIZE as usize];
+    for index in 0..chunks {
+        let len = (size - index * chunk_size).min(chunk_size) as usize;
+        reader.read_exact(&mut buf[..len])?;
+        let ciphertext = match encryptor.take() {
+            Some(last) if index + 1 == chunks => last.encrypt_last(&buf[..len]),
+            Some(mut next) => {
+                let ciphertext = next.encrypt_next(&buf[..len]);
+                encryptor = Some(next);
+                ciphertext
+            }
+            None => unreachable!("encryptor is only consumed by the last chunk"),
+        }
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt segment"))?;
+        writer.write_all(&ciphertext)?;
+    }
+    writer.sync_all()?;
+    drop(writer);
+
+    std::fs::rename(&tmp_path, output).context("Failed to move encrypted copy into place")?;
+    Ok(())
+}
+
+/// Decrypt a local encrypted segment
+pub fn decrypt_file(keyring: &Keyring, input: &Path, output: &Path) -> Result<()> {
+    let mut reader = File::open(input).context("Failed to open encrypted segment")?;
+    let total = reader.metadata()?.len();
+    let mut buf = Vec::new();
+    (&mut reader).take(MAX_HEADER_SIZE).read_to_end(&mut buf)?;
+    let (header, header_len) = Header::decode(&buf)?;
+    let mut decryptor = Some(header.decryptor(keyring)?);
+
+    let mut writer = File::create(output).context("Failed to create output file")?;
+    let ranges = header.chunk_ranges(header_len, total);
+    let count = ranges.len();
+    for (index, range) in ranges.into_iter().enumerate() {
+        reader.seek(SeekFrom::Start(range.start))?;
+        let mut chunk = vec![0u8; (range.end - range.start) as usize];
+        reader.read_exact(&mut chunk)?;
+        writer.write_all(&decrypt_chunk(&mut decryptor, &chunk, index + 1 == count)?)?;
+    }
+    writer.sync_all()?;
+    info!(input = %input.display(), output = %output.display(), key_id = %header.key_id, "Decrypted segment");
+    Ok(())
+}
+
+/// Decrypt an object straight from storage, a chunk at a time
+pub async fn decrypt_object(
+    keyring: &Keyring,
+    backend: &dyn StorageBackend,
+    key: &str,
+    output: &Path,
+) -> Result<()> {
+    let object = backend
+        .head(key)
+        .await?
+        .with_context(|| format!("Object {key} not found in {}", backend.name()))?;
+    let head = backend
+        .get_range(key, 0..MAX_HEADER_SIZE.min(object.size))
+        .await?;
+    let (header, header_len) = Header::decode(&head)?;
+    let mut decryptor = Some(header.decryptor(keyring)?);
+
+    let mut writer = File::create(output).context("Failed to create output file")?;
+    let ranges = header.chunk_ranges(header_len, object.size);
+    let count = ranges.len();
+    for (index, range) in ranges.into_iter().enumerate() {
+        let chunk = backend.get_range(key, range).await?;
+        writer.write_all(&decrypt_chunk(&mut decryptor, &chunk, index + 1 == count)?)?;
+    }
+    writer.sync_all()?;
+    info!(key = %key, output = %output.display(), key_id = %header.key_id, "Decrypted segment");
+    Ok(())
+}
+
+fn decrypt_chunk(
+    decryptor: &mut Option<DecryptorBE32<Aes256Gcm>>,
+    chunk: &[u8],
+    last: bool,
+) -> Result<Vec<u8>> {
+    let result = if last {
+        decryptor
+            .take()
+            .context("Segment has data after its final chunk")?
+            .decrypt_last(chunk)
+    } else {
+        decryptor
+            .as_mut()
+            .context("Segment has data after its final chunk")?
+            .decrypt_next(chunk)
+    };
+    result.map_err(|_| anyhow::anyhow!("Segment failed authentication (corrupt or wrong key)"))
+}
@@ -0,0 +1,285 @@
+use super::dead_letter::DeadLetter;
+use super::journal::{JournalEntry, SegmentState};
+use super::{encryption, multipart, DeadLetters, UploadJournal};
+use crate::config::{RecordingConfig, UploadConfig};
+use crate::metrics;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+
+/// How often the janitor checks retention and disk usage
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Keeps uploaded segments around for `local_retention_minutes` as a local
+/// redundancy cache, caps the age and size of the dead-letter area, and
+/// evicts segments when temp_dir crosses the disk high watermark.
+pub struct LocalJanitor {
+    temp_dir: PathBuf,
+    journal: Arc<UploadJournal>,
+    dead_letters: Arc<DeadLetters>,
+    retention: chrono::Duration,
+    dead_letter_max_age: Option<chrono::Duration>,
+    dead_letter_max_bytes: Option<u64>,
+    high_watermark_percent: u8,
+    low_watermark_percent: u8,
+    capacity_bytes: Option<u64>,
+}
+
+impl LocalJanitor {
+    pub fn new(
+        recording: &RecordingConfig,
+        upload: &UploadConfig,
+        journal: Arc<UploadJournal>,
+        dead_letters: Arc<DeadLetters>,
+    ) -> Self {
+        Self {
+            temp_dir: recording.temp_dir.clone(),
+            journal,
+            dead_letters,
+            retention: chrono::Duration::minutes(recording.local_retention_minutes as i64),
+            dead_letter_max_age: (upload.dead_letter_max_age_hours > 0)
+                .then(|| chrono::Duration::hours(upload.dead_letter_max_age_hours as i64)),
+            dead_letter_max_bytes: upload.dead_letter_max_bytes,
+            high_watermark_percent: recording.disk_high_watermark_percent,
+            low_watermark_percent: recording.disk_low_watermark_percent,
+            capacity_bytes: recording.local_capacity_bytes,
+        }
+    }
+
+    /// Run the janitor loop forever
+    pub async fn run(self) {
+        info!(
+            retention_mins = self.retention.num_minutes(),
+            high_watermark = self.high_watermark_percent,
+            low_watermark = self.low_watermark_percent,
+            "Local retention janitor started"
+        );
+
+        let mut ticker = interval(SWEEP_INTERVAL);
+        loop {
+            ticker.tick().await;
+
+            self.expire_retained().await;
+            self.expire_dead_letters().await;
+            if let Err(e) = self.relieve_disk_pressure().await {
+                warn!(error = %e, "Disk pressure check failed");
+            }
+        }
+    }
+
+    /// Delete uploaded segments older than the retention window.
+    ///
+    /// Partially uploaded segments stop being backfilled once their recording
+    /// is older than the window; without one they are kept until backfilled.
+    async fn expire_retained(&self) {
+        let cutoff = Utc::now() - self.retention;
+        for entry in self.journal.entries() {
+            let expired = match entry.state {
+                SegmentState::Uploaded => entry.updated_at <= cutoff,
+                SegmentState::Partial => !self.retention.is_zero() && entry.segment.start <= cutoff,
+                _ => false,
+            };
+            if expired {
+                self.evict(&entry, "retention").await;
+            }
+        }
+    }
+
+    /// Delete dead-lettered segments past their maximum age, then the oldest
+    /// ones while the dead-letter area is over its size cap
+    async fn expire_dead_letters(&self) {
+        if self.dead_letter_max_age.is_none() && self.dead_letter_max_bytes.is_none() {
+            return;
+        }
+        let dead_letters = match self.dead_letters.list().await {
+            Ok(dead_letters) => dead_letters,
+            Err(e) => {
+                warn!(error = %e, "Failed to list dead-lettered segments");
+                return;
+            }
+        };
+
+        let cutoff = self.dead_letter_max_age.map(|age| Utc::now() - age);
+        let mut total: u64 = dead_letters.iter().map(|d| d.size_bytes).sum();
+        for dead_letter in &dead_letters {
+            let reason = if cutoff.is_some_and(|cutoff| dead_letter.failed_at <= cutoff) {
+                "dead_letter_age"
+            } else if self.dead_letter_max_bytes.is_some_and(|max| total > max) {
+                "dead_letter_size"
+            } else {
+                continue;
+            };
+            total = total.saturating_sub(self.evict_dead_letter(dead_letter, reason).await);
+        }
+    }
+
+    /// Evict oldest-uploaded (or partially uploaded) segments first, then
+    /// dead-lettered ones, then un-uploaded ones as a last resort, until usage
+    /// drops below the low watermark
+    async fn relieve_disk_pressure(&self) -> Result<()> {
+        let (used, capacity) = self.disk_usage().await?;
+        if capacity == 0 {
+            return Ok(());
+        }
+
+        let percent = used as f64 * 100.0 / capacity as f64;
+        metrics::LOCAL_DISK_USAGE.set(percent);
+        if percent < f64::from(self.high_watermark_percent) {
+            return Ok(());
+        }
+
+        let target = capacity * u64::from(self.low_watermark_percent) / 100;
+        let mut remaining = used.saturating_sub(target);
+        warn!(
+            usage_percent = percent,
+            to_free_mb = remaining / 1_048_576,
+            "Disk high watermark reached, evicting local segments"
+        );
+
+        let mut entries = self.journal.entries();
+        let (mut uploaded, mut not_uploaded): (Vec<_>, Vec<_>) = entries
+            .drain(..)
+            .filter(|e| e.state != SegmentState::Uploading)
+            .partition(|e| matches!(e.state, SegmentState::Uploaded | SegmentState::Partial));
+        uploaded.sort_by_key(|e| e.updated_at);
+        not_uploaded.sort_by_key(|e| e.segment.start);
+
+        for entry in &uploaded {
+            if remaining == 0 {
+                return Ok(());
+            }
+            remaining = remaining.saturating_sub(self.evict(entry, "disk_pressure").await);
+        }
+
+        // Oldest failure first
+        let dead_letters = self.dead_letters.list().await.unwrap_or_else(|e| {
+            warn!(error = %e, "Failed to list dead-lettered segments");
+            Vec::new()
+        });
+        for dead_letter in &dead_letters {
+            if remaining == 0 {
+                return Ok(());
+            }
+            remaining = remaining.saturating_sub(
+                self.evict_dead_letter(dead_letter, "disk_pressure_dead_letter")
+                    .await,
+            );
+        }
+
+        for entry in &not_uploaded {
+            if remaining == 0 {
+                return Ok(());
+            }
+            error!(
+                camera_id = %entry.segment.camera_id,
+                path = %entry.segment.local_path.display(),
+                "DISK FULL: deleting segment that was never uploaded, footage is lost"
+            );
+            remaining =
+                remaining.saturating_sub(self.evict(entry, "disk_pressure_not_uploaded").await);
+        }
+
+        if remaining > 0 {
+            error!(
+                still_over_mb = remaining / 1_048_576,
+                "Disk still above low watermark after evicting every tracked segment"
+            );
+        }
+        Ok(())
+    }
+
+    /// Delete a segment's local file, returning the number of bytes freed
+    async fn evict(&self, entry: &JournalEntry, reason: &str) -> u64 {
+        let path = &entry.segment.local_path;
+        let size = tokio::fs::metadata(path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!(error = %e, path = %path.display(), "Failed to evict local segment");
+                return 0;
+            }
+        }
+
+        if let Err(e) = multipart::remove_all_state(path).await {
+            warn!(error = %e, path = %path.display(), "Failed to remove multipart state");
+        }
+        if let Err(e) = encryption::remove_encrypted_copy(path).await {
+            warn!(error = %e, path = %path.display(), "Failed to remove encrypted copy");
+        }
+
+        self.journal.forget(path);
+        metrics::SEGMENTS_EVICTED
+            .with_label_values(&[&entry.segment.camera_id, reason])
+            .inc();
+        info!(
+            camera_id = %entry.segment.camera_id,
+            path = %path.display(),
+            reason = reason,
+            "Evicted local segment"
+        );
+        size
+    }
+
+    /// Delete a dead-lettered segment, returning the number of bytes freed
+    async fn evict_dead_letter(&self, dead_letter: &DeadLetter, reason: &str) -> u64 {
+        match self.dead_letters.remove(dead_letter).await {
+            Ok(size) => {
+                metrics::SEGMENTS_EVICTED
+                    .with_label_values(&[&dead_letter.segment.camera_id, reason])
+                    .inc();
+                warn!(
+                    camera_id = %dead_letter.segment.camera_id,
+                    id = %dead_letter.id(),
+                    stored_on = ?dead_letter.uploaded_to,
+                    reason = reason,
+                    "Evicted dead-lettered segment"
+                );
+                size
+            }
+            Err(e) => {
+                warn!(error = %e, id = %dead_letter.id(), "Failed to evict dead-lettered segment");
+                0
+            }
+        }
+    }
+
+    /// Current usage and capacity of temp_dir in bytes
+    async fn disk_usage(&self) -> Result<(u64, u64)> {
+        let temp_dir = self.temp_dir.clone();
+        let capacity_bytes = self.capacity_bytes;
+
+        tokio::task::spawn_blocking(move || match capacity_bytes {
+            Some(capacity) => Ok((dir_size(&temp_dir)?, capacity)),
+            None => {
+                let total = fs4::total_space(&temp_dir).context("Failed to stat temp_dir")?;
+                let available =
+                    fs4::available_space(&temp_dir).context("Failed to stat temp_dir")?;
+                Ok((total.saturating_sub(available), total))
+            }
+        })
+        .await?
+    }
+}
+
+/// Total size of all files below `dir`
+fn dir_size(dir: &Path) -> Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(dir).context("Failed to read temp_dir")? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            total += dir_size(&entry.path())?;
+        } else {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
+}
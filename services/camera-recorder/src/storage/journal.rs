@@ -0,0 +1,303 @@
+use super::SegmentInfo;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Journal file name, created directly under the recording temp dir
+const JOURNAL_FILE: &str = "upload-journal.jsonl";
+/// The file is rewritten at runtime once it holds this many lines...
+const COMPACT_MIN_LINES: usize = 1000;
+/// ...and more than this many lines per live entry
+const COMPACT_LINES_PER_ENTRY: usize = 4;
+
+/// Lifecycle state of a segment in the upload pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentState {
+    Recorded,
+    Uploading,
+    Uploaded,
+    /// Stored on enough destinations to be safe, still missing on others
+    Partial,
+    Failed,
+}
+
+/// A single journal line: the latest known state of one segment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    #[serde(flatten)]
+    pub segment: SegmentInfo,
+    pub state: SegmentState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Destinations that already hold a verified copy
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub uploaded_to: BTreeSet<String>,
+    /// Times the segment was re-driven out of the dead-letter area
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub redrives: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Append-only, fsync'd record of segment lifecycle transitions.
+///
+/// Every state change is appended as a JSON line. On open the file is
+/// replayed, segments whose local file is gone are dropped and the result
+/// is rewritten, so the journal only holds segments still on disk. While
+/// running, the file is rewritten the same way once superseded lines
+/// outnumber live entries, so it stays bounded on long-lived pods.
+pub struct UploadJournal {
+    path: PathBuf,
+    inner: Mutex<JournalInner>,
+}
+
+struct JournalInner {
+    file: File,
+    entries: HashMap<PathBuf, JournalEntry>,
+    /// Lines in the file, including superseded ones
+    lines: usize,
+}
+
+impl UploadJournal {
+    /// Open (or create) the journal under `dir` and compact it
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).context("Failed to create journal directory")?;
+        let path = dir.join(JOURNAL_FILE);
+
+        let mut entries = HashMap::new();
+        if path.exists() {
+            let file = File::open(&path).context("Failed to open upload journal")?;
+            for (line_no, line) in BufReader::new(file).lines().enumerate() {
+                let line = line.context("Failed to read upload journal")?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<JournalEntry>(&line) {
+                    Ok(entry) => {
+                        entries.insert(entry.segment.local_path.clone(), entry);
+                    }
+                    Err(e) => {
+                        // A torn final line is expected if we crashed mid-write
+                        warn!(
+                            line = line_no + 1,
+                            error = %e,
+                            "Skipping malformed upload journal entry"
+                        );
+                    }
+                }
+            }
+        }
+
+        // Drop segments whose local file is already gone
+        entries.retain(|path: &PathBuf, _| path.exists());
+
+        let file = rewrite(&path, &entries)?;
+
+        info!(
+            path = %path.display(),
+            pending = entries.len(),
+            "Upload journal opened"
+        );
+
+        Ok(Self {
+            path,
+            inner: Mutex::new(JournalInner {
+                file,
+                lines: entries.len(),
+                entries,
+            }),
+        })
+    }
+
+    /// Record a state transition for a segment
+    pub fn record(&self, segment: &SegmentInfo, state: SegmentState) -> Result<()> {
+        self.append(segment, state, None)
+    }
+
+    /// Record that a segment exhausted its retries, along with the last error
+    pub fn record_failure(&self, segment: &SegmentInfo, error: &str) -> Result<()> {
+        self.append(segment, SegmentState::Failed, Some(error.to_string()))
+    }
+
+    /// Record that one destination now holds a verified copy of the segment
+    pub fn record_destination(&self, segment: &SegmentInfo, destination: &str) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut entry = inner
+            .entries
+            .get(&segment.local_path)
+            .cloned()
+            .unwrap_or_else(|| JournalEntry {
+                segment: segment.clone(),
+                state: SegmentState::Uploading,
+                error: None,
+                uploaded_to: BTreeSet::new(),
+                redrives: 0,
+                updated_at: Utc::now(),
+            });
+        entry.uploaded_to.insert(destination.to_string());
+        entry.updated_at = Utc::now();
+        self.write(&mut inner, entry)
+    }
+
+    /// Track a segment re-driven from the dead-letter area, keeping what its
+    /// earlier attempts achieved
+    pub fn record_redrive(
+        &self,
+        segment: &SegmentInfo,
+        uploaded_to: BTreeSet<String>,
+        redrives: u32,
+    ) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = JournalEntry {
+            segment: segment.clone(),
+            state: SegmentState::Recorded,
+            error: None,
+            uploaded_to,
+            redrives,
+            updated_at: Utc::now(),
+        };
+        self.write(&mut inner, entry)
+    }
+
+    /// Current state of the segment at `path`
+    pub fn entry(&self, path: &Path) -> Option<JournalEntry> {
+        self.inner.lock().unwrap().entries.get(path).cloned()
+    }
+
+    /// Destinations that already hold a verified copy of the segment at `path`
+    pub fn uploaded_to(&self, path: &Path) -> BTreeSet<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .entries
+            .get(path)
+            .map(|e| e.uploaded_to.clone())
+            .unwrap_or_default()
+    }
+
+    /// Stop tracking a segment whose local file has been deleted.
+    ///
+    /// Nothing is written: the next compaction drops entries without a file.
+    pub fn forget(&self, path: &Path) {
+        self.inner.lock().unwrap().entries.remove(path);
+    }
+
+    /// Snapshot of every tracked segment
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.inner
+            .lock()
+            .unwrap()
+            .entries
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Whether the journal is tracking the segment at `path`
+    pub fn contains(&self, path: &Path) -> bool {
+        self.inner.lock().unwrap().entries.contains_key(path)
+    }
+
+    /// Segments that were recorded but not yet confirmed uploaded, oldest first
+    pub fn pending(&self) -> Vec<SegmentInfo> {
+        let inner = self.inner.lock().unwrap();
+        let mut pending: Vec<SegmentInfo> = inner
+            .entries
+            .values()
+            .filter(|e| e.state != SegmentState::Uploaded)
+            .map(|e| e.segment.clone())
+            .collect();
+        pending.sort_by_key(|s| s.start);
+        pending
+    }
+
+    fn append(
+        &self,
+        segment: &SegmentInfo,
+        state: SegmentState,
+        error: Option<String>,
+    ) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let (uploaded_to, redrives) = inner
+            .entries
+            .get(&segment.local_path)
+            .map(|e| (e.uploaded_to.clone(), e.redrives))
+            .unwrap_or_default();
+        let entry = JournalEntry {
+            segment: segment.clone(),
+            state,
+            error,
+            uploaded_to,
+            redrives,
+            updated_at: Utc::now(),
+        };
+        self.write(&mut inner, entry)
+    }
+
+    /// Append an entry to the file and make it the segment's current state
+    fn write(&self, inner: &mut JournalInner, entry: JournalEntry) -> Result<()> {
+        let line = serde_json::to_string(&entry)?;
+        writeln!(inner.file, "{line}")
+            .and_then(|_| inner.file.sync_data())
+            .with_context(|| format!("Failed to append to {}", self.path.display()))?;
+
+        inner
+            .entries
+            .insert(entry.segment.local_path.clone(), entry);
+        inner.lines += 1;
+
+        if inner.lines >= COMPACT_MIN_LINES
+            && inner.lines > inner.entries.len() * COMPACT_LINES_PER_ENTRY
+        {
+            self.compact(inner);
+        }
+        Ok(())
+    }
+
+    /// Rewrite the file with only the live entries
+    fn compact(&self, inner: &mut JournalInner) {
+        match rewrite(&self.path, &inner.entries) {
+            Ok(file) => {
+                info!(
+                    path = %self.path.display(),
+                    dropped_lines = inner.lines - inner.entries.len(),
+                    entries = inner.entries.len(),
+                    "Upload journal compacted"
+                );
+                inner.file = file;
+                inner.lines = inner.entries.len();
+            }
+            // The old file is still intact and appended to; retried on the next write
+            Err(e) => warn!(error = %e, "Failed to compact upload journal"),
+        }
+    }
+}
+
+/// Atomically replace the journal at `path` with `entries` and open it for
+/// appending
+fn rewrite(path: &Path, entries: &HashMap<PathBuf, JournalEntry>) -> Result<File> {
+    let tmp_path = path.with_extension("jsonl.tmp");
+    {
+        let mut tmp = File::create(&tmp_path).context("Failed to write upload journal")?;
+        for entry in entries.values() {
+            writeln!(tmp, "{}", serde_json::to_string(entry)?)?;
+        }
+        tmp.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path).context("Failed to replace upload journal")?;
+
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .context("Failed to open upload journal for append")
+}
@@ -0,0 +1,351 @@
+use super::metadata::recorder_host;
+use super::SegmentInfo;
+use crate::config::{CameraConfig, StorageConfig};
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Timelike, Utc};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+/// Layout the recorder has always used
+pub const DEFAULT_KEY_TEMPLATE: &str = "{camera_id}/{date}/{filename}";
+
+/// One piece of a parsed key template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    CameraId,
+    CameraName,
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Date,
+    Start,
+    End,
+    Sequence,
+    Host,
+    Filename,
+}
+
+impl Placeholder {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "camera_id" => Self::CameraId,
+            "camera_name" => Self::CameraName,
+            "year" => Self::Year,
+            "month" => Self::Month,
+            "day" => Self::Day,
+            "hour" => Self::Hour,
+            "minute" => Self::Minute,
+            "second" => Self::Second,
+            "date" => Self::Date,
+            "start" => Self::Start,
+            "end" => Self::End,
+            "sequence" => Self::Sequence,
+            "host" => Self::Host,
+            "filename" => Self::Filename,
+            _ => return None,
+        })
+    }
+
+    /// Regex for the rendered value, used to read dates back out of keys
+    fn pattern(self) -> &'static str {
+        match self {
+            Self::Year => r"\d{4}",
+            Self::Month | Self::Day | Self::Hour | Self::Minute | Self::Second => r"\d{2}",
+            Self::Date => r"\d{8}",
+            Self::Start | Self::End => r"\d{2}-\d{2}-\d{2}",
+            Self::Sequence => r"\d+",
+            Self::CameraId | Self::CameraName | Self::Host | Self::Filename => r"[^/]+",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// Split a template into literals and `{placeholder}`s
+fn parse(template: &str) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        if open > 0 {
+            parts.push(Part::Literal(rest[..open].to_string()));
+        }
+        let close = rest[open..]
+            .find('}')
+            .with_context(|| format!("Unclosed placeholder in key template: {template}"))?;
+        let name = &rest[open + 1..open + close];
+        let placeholder = Placeholder::from_name(name).with_context(|| {
+            format!("Unknown placeholder {{{name}}} in key template: {template}")
+        })?;
+        parts.push(Part::Placeholder(placeholder));
+        rest = &rest[open + close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+/// Whether two segments of one camera can never render to the same key
+fn is_unique_per_segment(parts: &[Part]) -> bool {
+    let has = |p: Placeholder| {
+        parts
+            .iter()
+            .any(|part| matches!(part, Part::Placeholder(q) if *q == p))
+    };
+    let has_date = has(Placeholder::Date)
+        || (has(Placeholder::Year) && has(Placeholder::Month) && has(Placeholder::Day));
+    let has_time = has(Placeholder::Start)
+        || (has(Placeholder::Hour) && has(Placeholder::Minute) && has(Placeholder::Second));
+    has(Placeholder::Filename) || (has_date && has_time)
+}
+
+/// Whether keys name their camera, so retention and listings can tell cameras apart
+fn names_camera(parts: &[Part]) -> bool {
+    parts.iter().any(|part| {
+        matches!(
+            part,
+            Part::Placeholder(Placeholder::CameraId | Placeholder::CameraName)
+        )
+    })
+}
+
+/// Camera names are free text; keep them to characters that are safe in keys
+fn key_safe(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn render(parts: &[Part], segment: &SegmentInfo, segment_duration_secs: u64) -> String {
+    // Keys are dated by the segment's UTC start time
+    let start: DateTime<Utc> = segment.start;
+    let end = start + chrono::Duration::seconds(segment_duration_secs as i64);
+    let filename = segment
+        .local_path
+        .file_name()
+        .and_then(|f| f.to_str())
+        .unwrap_or("unknown");
+
+    let mut key = String::new();
+    for part in parts {
+        match part {
+            Part::Literal(text) => key.push_str(text),
+            Part::Placeholder(placeholder) => {
+                let value = match placeholder {
+                    Placeholder::CameraId => segment.camera_id.clone(),
+                    Placeholder::CameraName => key_safe(&segment.camera_name),
+                    Placeholder::Year => start.format("%Y").to_string(),
+                    Placeholder::Month => start.format("%m").to_string(),
+                    Placeholder::Day => start.format("%d").to_string(),
+                    Placeholder::Hour => start.format("%H").to_string(),
+                    Placeholder::Minute => start.format("%M").to_string(),
+                    Placeholder::Second => start.format("%S").to_string(),
+                    Placeholder::Date => start.format("%Y%m%d").to_string(),
+                    Placeholder::Start => start.format("%H-%M-%S").to_string(),
+                    Placeholder::End => end.format("%H-%M-%S").to_string(),
+                    Placeholder::Sequence => {
+                        // Slot of the day the segment started in, e.g. 000-095 for 15 minutes
+                        let slot = u64::from(start.num_seconds_from_midnight())
+                            / segment_duration_secs.max(1);
+                        format!("{slot:03}")
+                    }
+                    Placeholder::Host => recorder_host().to_string(),
+                    Placeholder::Filename => filename.to_string(),
+                };
+                key.push_str(&value);
+            }
+        }
+    }
+    key
+}
+
+/// A camera's compiled key template
+#[derive(Debug, Clone)]
+pub struct KeyTemplate {
+    parts: Vec<Part>,
+    segment_duration_secs: u64,
+    /// Longest leading part of every key that is fixed for this camera
+    prefix: String,
+    /// Matches this camera's keys, capturing their date
+    matcher: Regex,
+}
+
+impl KeyTemplate {
+    fn for_camera(template: &str, camera: &CameraConfig) -> Result<Self> {
+        let parts = parse(template)?;
+        anyhow::ensure!(
+            !template.starts_with('/'),
+            "Key template must not start with '/': {template}"
+        );
+        anyhow::ensure!(
+            matches!(parts.last(), Some(Part::Placeholder(_)))
+                || matches!(parts.last(), Some(Part::Literal(text)) if !text.ends_with('/')),
+            "Key template must not end with '/': {template}"
+        );
+        anyhow::ensure!(
+            is_unique_per_segment(&parts),
+            "Key template for {} can produce colliding keys; it needs {{filename}}, or a date \
+             ({{date}} or {{year}}/{{month}}/{{day}}) and a time ({{start}} or \
+             {{hour}}/{{minute}}/{{second}}): {template}",
+            camera.id
+        );
+        anyhow::ensure!(
+            names_camera(&parts),
+            "Key template for {} needs {{camera_id}} or {{camera_name}}, otherwise retention \
+             can't tell its objects from other cameras': {template}",
+            camera.id
+        );
+
+        let camera_value = |placeholder: Placeholder| match placeholder {
+            Placeholder::CameraId => Some(camera.id.clone()),
+            Placeholder::CameraName => Some(key_safe(&camera.name)),
+            _ => None,
+        };
+
+        let mut prefix = String::new();
+        let mut prefix_done = false;
+        let mut pattern = String::from("^");
+        let mut captured = HashSet::new();
+        for part in &parts {
+            let (fixed, regex) = match part {
+                Part::Literal(text) => (Some(text.clone()), regex::escape(text)),
+                Part::Placeholder(placeholder) => match camera_value(*placeholder) {
+                    Some(value) => (Some(value.clone()), regex::escape(&value)),
+                    None => {
+                        let name = match placeholder {
+                            Placeholder::Year => "year",
+                            Placeholder::Month => "month",
+                            Placeholder::Day => "day",
+                            Placeholder::Date => "date",
+                            _ => "",
+                        };
+                        // Only the first occurrence of a date field is captured
+                        let regex = if !name.is_empty() && captured.insert(name) {
+                            format!("(?P<{name}>{})", placeholder.pattern())
+                        } else {
+                            format!("(?:{})", placeholder.pattern())
+                        };
+                        (None, regex)
+                    }
+                },
+            };
+            match fixed {
+                Some(text) if !prefix_done => prefix.push_str(&text),
+                _ => prefix_done = true,
+            }
+            pattern.push_str(&regex);
+        }
+        pattern.push('$');
+
+        Ok(Self {
+            parts,
+            segment_duration_secs: camera.segment_duration_secs,
+            prefix,
+            matcher: Regex::new(&pattern).context("Failed to compile key template")?,
+        })
+    }
+
+    pub fn render(&self, segment: &SegmentInfo) -> String {
+        render(&self.parts, segment, self.segment_duration_secs)
+    }
+
+    /// Prefix to list when looking for this camera's objects
+    pub fn list_prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Day a key belongs to, `None` if the key isn't this camera's or has no date
+    pub fn date_of(&self, key: &str) -> Option<NaiveDate> {
+        let captures = self.matcher.captures(key)?;
+        if let Some(date) = captures.name("date") {
+            return NaiveDate::parse_from_str(date.as_str(), "%Y%m%d").ok();
+        }
+        let field = |name: &str| captures.name(name)?.as_str().parse::<u32>().ok();
+        NaiveDate::from_ymd_opt(field("year")? as i32, field("month")?, field("day")?)
+    }
+
+    /// Whether a key was produced by this camera's template
+    pub fn matches(&self, key: &str) -> bool {
+        self.matcher.is_match(key)
+    }
+}
+
+/// Object key layout for every camera
+#[derive(Debug, Clone)]
+pub struct KeyLayout {
+    default: Vec<Part>,
+    cameras: HashMap<String, KeyTemplate>,
+}
+
+impl KeyLayout {
+    /// Compile and validate the storage template and any per-camera overrides
+    pub fn new(storage: &StorageConfig, cameras: &[CameraConfig]) -> Result<Self> {
+        let default = parse(&storage.key_template)?;
+        let mut templates = HashMap::new();
+        for camera in cameras {
+            let template = camera
+                .key_template
+                .as_deref()
+                .unwrap_or(&storage.key_template);
+            templates.insert(
+                camera.id.clone(),
+                KeyTemplate::for_camera(template, camera)?,
+            );
+        }
+
+        // Segments from different cameras recorded at the same moment must not
+        // share a key
+        let probe_time = Utc::now().with_nanosecond(0).unwrap_or_else(Utc::now);
+        let mut seen = HashMap::new();
+        for camera in cameras {
+            let segment = SegmentInfo {
+                camera_id: camera.id.clone(),
+                camera_name: camera.name.clone(),
+                local_path: format!("{}_{}.mp4", probe_time.format("%Y%m%d_%H%M%S"), camera.id)
+                    .into(),
+                start: probe_time,
+                end: None,
+                duration_secs: None,
+            };
+            let key = templates[&camera.id].render(&segment);
+            if let Some(other) = seen.insert(key.clone(), camera.id.clone()) {
+                anyhow::bail!(
+                    "Key templates for cameras {other} and {} produce colliding keys (e.g. {key})",
+                    camera.id
+                );
+            }
+        }
+
+        Ok(Self {
+            default,
+            cameras: templates,
+        })
+    }
+
+    /// Object key for a segment
+    pub fn key_for(&self, segment: &SegmentInfo) -> String {
+        match self.cameras.get(&segment.camera_id) {
+            Some(template) => template.render(segment),
+            // A camera removed from config since the segment was journaled
+            None => render(&self.default, segment, 0),
+        }
+    }
+
+    pub fn camera(&self, camera_id: &str) -> Option<&KeyTemplate> {
+        self.cameras.get(camera_id)
+    }
+}
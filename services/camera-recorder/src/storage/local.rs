@@ -0,0 +1,301 @@
+use super::backend::{ObjectInfo, StorageBackend};
+use super::checksum::{ChecksumMismatch, Sha256Digest, SHA256_METADATA_KEY};
+use super::metadata::SegmentMetadata;
+use super::throttle::UploadThrottle;
+use crate::config::DestinationConfig;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+/// Object metadata lives in a parallel tree so listings only see segments
+const META_DIR: &str = ".meta";
+/// Copies land here first and are renamed into place once verified
+const PARTIAL_DIR: &str = ".partial";
+
+/// Stores segments on a local or NFS-mounted filesystem, laid out with the
+/// same keys the S3 backend would use
+pub struct LocalBackend {
+    name: String,
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(config: &DestinationConfig) -> Result<Self> {
+        let root = config
+            .local_path
+            .clone()
+            .context("storage.local_path is required for the local backend")?;
+        Ok(Self {
+            name: config.name.clone(),
+            root,
+        })
+    }
+
+    /// Filesystem path for a key, rejecting keys that would escape the root
+    fn object_path(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        anyhow::ensure!(
+            relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_))),
+            "Invalid object key: {key}"
+        );
+        Ok(self.root.join(relative))
+    }
+
+    fn meta_path(&self, key: &str) -> Result<PathBuf> {
+        self.object_path(key)?;
+        Ok(self.root.join(META_DIR).join(format!("{key}.json")))
+    }
+
+    async fn read_metadata(&self, key: &str) -> Result<HashMap<String, String>> {
+        match tokio::fs::read(self.meta_path(key)?).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes).unwrap_or_default()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e).context("Failed to read object metadata"),
+        }
+    }
+}
+
+/// `tokio::fs::copy`, paced by the upload throttle
+async fn copy_throttled(from: &Path, to: &Path, throttle: &UploadThrottle) -> std::io::Result<()> {
+    let length = tokio::fs::metadata(from).await?.len();
+    let mut out = tokio::fs::File::create(to).await?;
+    let mut chunks = std::pin::pin!(throttle.read_file(from, 0, length));
+    while let Some(chunk) = chunks.next().await {
+        out.write_all(&chunk?).await?;
+    }
+    out.flush().await
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn describe(&self) -> String {
+        format!("local directory {}", self.root.display())
+    }
+
+    async fn ensure_ready(&self) -> Result<()> {
+        for dir in [
+            self.root.clone(),
+            self.root.join(META_DIR),
+            self.root.join(PARTIAL_DIR),
+        ] {
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        info!(root = %self.root.display(), "Local storage ready");
+        Ok(())
+    }
+
+    async fn put(
+        &self,
+        local_path: &Path,
+        key: &str,
+        metadata: &SegmentMetadata,
+        throttle: &UploadThrottle,
+    ) -> Result<()> {
+        let dest = self.object_path(key)?;
+        let partial = self.root.join(PARTIAL_DIR).join(key.replace('/', "__"));
+
+        let digest = Sha256Digest::of_file(local_path).await?;
+        throttle.wait_for_window().await;
+        copy_throttled(local_path, &partial, throttle)
+            .await
+            .context("Failed to copy segment to local storage")?;
+
+        // Verify what actually landed on the target filesystem
+        let copied = Sha256Digest::of_file(&partial).await?;
+        if copied != digest {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(ChecksumMismatch {
+                key: key.to_string(),
+                detail: format!("sha256 {} != {}", copied.to_hex(), digest.to_hex()),
+            }
+            .into());
+        }
+        tokio::fs::File::open(&partial).await?.sync_all().await?;
+
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(&partial, &dest)
+            .await
+            .context("Failed to move segment into place")?;
+
+        let mut object_metadata = metadata.to_object_metadata();
+        object_metadata.insert(SHA256_METADATA_KEY.to_string(), digest.to_hex());
+        let meta_path = self.meta_path(key)?;
+        if let Some(parent) = meta_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&meta_path, serde_json::to_vec(&object_metadata)?)
+            .await
+            .context("Failed to write object metadata")?;
+
+        info!(key = %key, path = %dest.display(), "Stored segment locally");
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, body: Vec<u8>, _content_type: &str) -> Result<()> {
+        let dest = self.object_path(key)?;
+        let partial = self.root.join(PARTIAL_DIR).join(key.replace('/', "__"));
+
+        let mut file = tokio::fs::File::create(&partial)
+            .await
+            .context("Failed to create object")?;
+        file.write_all(&body).await?;
+        file.sync_all().await?;
+
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(&partial, &dest)
+            .await
+            .context("Failed to move object into place")?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.object_path(key)?).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read object"),
+        }
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectInfo>> {
+        let path = self.object_path(key)?;
+        let stat = match tokio::fs::metadata(&path).await {
+            Ok(stat) if stat.is_file() => stat,
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to stat object"),
+        };
+
+        Ok(Some(ObjectInfo {
+            key: key.to_string(),
+            size: stat.len(),
+            last_modified: stat.modified().ok().map(DateTime::<Utc>::from),
+            metadata: self.read_metadata(key).await?,
+        }))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let root = self.root.clone();
+        let prefix = prefix.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut objects = Vec::new();
+            walk(&root, &root, &prefix, &mut objects)?;
+            Ok(objects)
+        })
+        .await?
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.object_path(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to delete object"),
+        }
+        let _ = tokio::fs::remove_file(self.meta_path(key)?).await;
+
+        // Tidy up directories (e.g. a whole day) once they are empty
+        if let Some(parent) = path.parent() {
+            if parent != self.root {
+                let _ = tokio::fs::remove_dir(parent).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        let path = self.object_path(key)?;
+        tokio::task::spawn_blocking(move || {
+            let mut file = std::fs::File::open(&path).context("Failed to open object")?;
+            file.seek(SeekFrom::Start(range.start))?;
+            let mut buf = Vec::new();
+            file.take(range.end.saturating_sub(range.start))
+                .read_to_end(&mut buf)
+                .context("Failed to read object")?;
+            Ok(buf)
+        })
+        .await?
+    }
+
+    async fn abort_stale_uploads(&self, max_age: Duration) -> Result<usize> {
+        let cutoff = SystemTime::now() - max_age;
+        let mut removed = 0;
+        let mut entries = match tokio::fs::read_dir(self.root.join(PARTIAL_DIR)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).context("Failed to read partial uploads"),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let stale = entry
+                .metadata()
+                .await?
+                .modified()
+                .map(|modified| modified < cutoff)
+                .unwrap_or(false);
+            if stale && tokio::fs::remove_file(entry.path()).await.is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Recursively collect objects below `dir` whose key starts with `prefix`
+fn walk(root: &Path, dir: &Path, prefix: &str, objects: &mut Vec<ObjectInfo>) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to list local storage"),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let stat = entry.metadata()?;
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let key = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if stat.is_dir() {
+            if dir == root && (key == META_DIR || key == PARTIAL_DIR) {
+                continue;
+            }
+            // Only descend into directories that can still contain matches
+            if prefix.starts_with(&format!("{key}/")) || key.starts_with(prefix) {
+                walk(root, &path, prefix, objects)?;
+            }
+        } else if key.starts_with(prefix) {
+            objects.push(ObjectInfo {
+                key,
+                size: stat.len(),
+                last_modified: stat.modified().ok().map(DateTime::<Utc>::from),
+                metadata: HashMap::new(),
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,352 @@
+use super::checksum::SHA256_METADATA_KEY;
+use super::journal::{SegmentState, UploadJournal};
+use super::metadata::SegmentMetadata;
+use super::StorageBackend;
+use crate::config::CameraConfig;
+use crate::metrics;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::time::{interval, Duration};
+use tracing::{info, warn};
+
+/// Manifests live under `manifests/<camera_id>/<YYYYMMDD>.json`
+pub const MANIFEST_PREFIX: &str = "manifests/";
+const MANIFEST_SUFFIX: &str = ".json";
+const MANIFEST_CONTENT_TYPE: &str = "application/json";
+/// How often finished days are checked for sealing
+const SEAL_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Slack on top of the longest segment before a finished day is sealed, for
+/// the last segment to be finalized and journaled
+const SEAL_GRACE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Key prefix holding a camera's manifests
+pub fn camera_prefix(camera_id: &str) -> String {
+    format!("{MANIFEST_PREFIX}{camera_id}/")
+}
+
+/// Manifest key for one camera and (UTC) day
+pub fn manifest_key(camera_id: &str, date: NaiveDate) -> String {
+    format!(
+        "{}{}{MANIFEST_SUFFIX}",
+        camera_prefix(camera_id),
+        date.format("%Y%m%d")
+    )
+}
+
+/// Day a manifest key covers, `None` for anything else under the prefix
+pub fn date_of(key: &str) -> Option<NaiveDate> {
+    let name = key.rsplit('/').next()?.strip_suffix(MANIFEST_SUFFIX)?;
+    NaiveDate::parse_from_str(name, "%Y%m%d").ok()
+}
+
+/// One uploaded segment, as listed in its day's manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub key: String,
+    pub start: DateTime<Utc>,
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub duration_secs: Option<f64>,
+    /// Size of the stored object (the encrypted copy, if encryption is on)
+    pub size_bytes: u64,
+    /// Hex SHA-256 of the stored object
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// Index of one camera's segments for one UTC day.
+///
+/// Consumers read this instead of listing the bucket. Once `sealed` is set
+/// the day is complete; a segment that still turns up later (e.g. re-driven
+/// from the dead-letter area) reopens it until it is sealed again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayManifest {
+    pub camera_id: String,
+    pub date: NaiveDate,
+    #[serde(default)]
+    pub sealed: bool,
+    #[serde(default)]
+    pub sealed_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+    /// Ordered by start time
+    pub segments: Vec<ManifestEntry>,
+}
+
+impl DayManifest {
+    fn new(camera_id: &str, date: NaiveDate) -> Self {
+        Self {
+            camera_id: camera_id.to_string(),
+            date,
+            sealed: false,
+            sealed_at: None,
+            updated_at: Utc::now(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Add a segment, replacing an earlier entry for the same key
+    fn upsert(&mut self, entry: ManifestEntry) {
+        self.segments.retain(|s| s.key != entry.key);
+        let index = self.segments.partition_point(|s| s.start <= entry.start);
+        self.segments.insert(index, entry);
+        self.updated_at = Utc::now();
+    }
+}
+
+/// Identifies one manifest object
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ManifestId {
+    destination: String,
+    camera_id: String,
+    date: NaiveDate,
+}
+
+impl ManifestId {
+    fn key(&self) -> String {
+        manifest_key(&self.camera_id, self.date)
+    }
+}
+
+/// Latest known state of a manifest, `None` until it has been read
+type ManifestSlot = Arc<tokio::sync::Mutex<Option<DayManifest>>>;
+
+/// Maintains the per-day manifests on every destination.
+///
+/// Updates to one manifest are serialized and each one rewrites the whole
+/// object, so readers always see a complete manifest. Only days that are
+/// still open are kept in memory.
+pub struct Manifests {
+    destinations: Vec<Arc<dyn StorageBackend>>,
+    journal: Arc<UploadJournal>,
+    open: Mutex<HashMap<ManifestId, ManifestSlot>>,
+    camera_ids: Vec<String>,
+    /// How long after midnight a day can still gain segments
+    seal_after: chrono::Duration,
+}
+
+impl Manifests {
+    pub fn new(
+        destinations: Vec<Arc<dyn StorageBackend>>,
+        cameras: &[CameraConfig],
+        journal: Arc<UploadJournal>,
+    ) -> Self {
+        let longest_segment = cameras
+            .iter()
+            .map(|c| c.segment_duration_secs)
+            .max()
+            .unwrap_or(0);
+        Self {
+            destinations,
+            journal,
+            open: Mutex::new(HashMap::new()),
+            camera_ids: cameras.iter().map(|c| c.id.clone()).collect(),
+            seal_after: chrono::Duration::seconds(longest_segment as i64) + SEAL_GRACE,
+        }
+    }
+
+    fn slot(&self, id: &ManifestId) -> ManifestSlot {
+        self.open
+            .lock()
+            .unwrap()
+            .entry(id.clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Add an uploaded segment to its day's manifest on `backend`
+    pub async fn record(
+        &self,
+        backend: &dyn StorageBackend,
+        key: &str,
+        metadata: &SegmentMetadata,
+    ) -> Result<()> {
+        // The stored object is the authority on size and checksum
+        let object = backend
+            .head(key)
+            .await?
+            .with_context(|| format!("Uploaded object {key} not found"))?;
+        let entry = ManifestEntry {
+            key: key.to_string(),
+            start: metadata.start,
+            end: metadata.end,
+            duration_secs: metadata.duration_secs,
+            size_bytes: object.size,
+            sha256: object.metadata.get(SHA256_METADATA_KEY).cloned(),
+        };
+
+        let id = ManifestId {
+            destination: backend.name().to_string(),
+            camera_id: metadata.camera_id.clone(),
+            date: metadata.start.date_naive(),
+        };
+        let slot = self.slot(&id);
+        let mut current = slot.lock().await;
+        if current.is_none() {
+            *current = Some(
+                load(backend, &id.key())
+                    .await?
+                    .unwrap_or_else(|| DayManifest::new(&id.camera_id, id.date)),
+            );
+        }
+        let manifest = current.as_mut().expect("manifest loaded above");
+        if manifest.sealed {
+            info!(
+                camera_id = %id.camera_id,
+                date = %id.date,
+                destination = %id.destination,
+                "Reopening sealed manifest for a late segment"
+            );
+            manifest.sealed = false;
+            manifest.sealed_at = None;
+        }
+        manifest.upsert(entry);
+        store(backend, &id.key(), manifest).await
+    }
+
+    /// Seal finished days in the background
+    pub async fn run(self: Arc<Self>) {
+        self.load_unsealed().await;
+
+        let mut ticker = interval(SEAL_CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            self.seal_finished().await;
+        }
+    }
+
+    /// Pick up days left open by a previous run
+    async fn load_unsealed(&self) {
+        let today = Utc::now().date_naive();
+        for backend in &self.destinations {
+            for camera_id in &self.camera_ids {
+                let objects = match backend.list(&camera_prefix(camera_id)).await {
+                    Ok(objects) => objects,
+                    Err(e) => {
+                        warn!(
+                            error = %e,
+                            camera_id = %camera_id,
+                            destination = %backend.name(),
+                            "Failed to list manifests"
+                        );
+                        continue;
+                    }
+                };
+                for object in objects {
+                    let Some(date) = date_of(&object.key).filter(|d| *d < today) else {
+                        continue;
+                    };
+                    match load(backend.as_ref(), &object.key).await {
+                        Ok(Some(manifest)) if !manifest.sealed => {
+                            let id = ManifestId {
+                                destination: backend.name().to_string(),
+                                camera_id: camera_id.clone(),
+                                date,
+                            };
+                            let slot = self.slot(&id);
+                            let mut current = slot.lock().await;
+                            if current.is_none() {
+                                *current = Some(manifest);
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!(error = %e, key = %object.key, "Failed to read manifest"),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Seal days that are over and have nothing left to upload
+    async fn seal_finished(&self) {
+        let now = Utc::now();
+        let pending: Vec<_> = self
+            .journal
+            .entries()
+            .into_iter()
+            .filter(|e| e.state != SegmentState::Uploaded)
+            .map(|e| (e.segment.camera_id, e.segment.start.date_naive()))
+            .collect();
+
+        let open: Vec<(ManifestId, ManifestSlot)> = self
+            .open
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, slot)| (id.clone(), slot.clone()))
+            .collect();
+        for (id, slot) in open {
+            let day_end = id.date.and_time(chrono::NaiveTime::MIN).and_utc() + chrono::Days::new(1);
+            if now < day_end + self.seal_after || pending.contains(&(id.camera_id.clone(), id.date))
+            {
+                continue;
+            }
+            let Some(backend) = self
+                .destinations
+                .iter()
+                .find(|d| d.name() == id.destination)
+            else {
+                continue;
+            };
+
+            let mut current = slot.lock().await;
+            let Some(manifest) = current.as_mut() else {
+                continue;
+            };
+            if !manifest.sealed {
+                manifest.sealed = true;
+                manifest.sealed_at = Some(now);
+                manifest.updated_at = now;
+                if let Err(e) = store(backend.as_ref(), &id.key(), manifest).await {
+                    // Stays open in memory and is retried next round
+                    manifest.sealed = false;
+                    manifest.sealed_at = None;
+                    warn!(error = %e, key = %id.key(), destination = %id.destination, "Failed to seal manifest");
+                    continue;
+                }
+                info!(
+                    camera_id = %id.camera_id,
+                    date = %id.date,
+                    destination = %id.destination,
+                    segments = manifest.segments.len(),
+                    "Manifest sealed"
+                );
+            }
+            drop(current);
+
+            // Clones are only taken under this lock, so nothing else holds
+            // the slot if only the map and this loop do
+            let mut open = self.open.lock().unwrap();
+            if Arc::strong_count(&slot) == 2 {
+                open.remove(&id);
+            }
+        }
+    }
+}
+
+async fn load(backend: &dyn StorageBackend, key: &str) -> Result<Option<DayManifest>> {
+    let Some(bytes) = backend.get(key).await? else {
+        return Ok(None);
+    };
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .with_context(|| format!("Failed to parse manifest {key}"))
+}
+
+async fn store(backend: &dyn StorageBackend, key: &str, manifest: &DayManifest) -> Result<()> {
+    let result = backend
+        .put_bytes(
+            key,
+            serde_json::to_vec_pretty(manifest)?,
+            MANIFEST_CONTENT_TYPE,
+        )
+        .await;
+    let outcome = if result.is_ok() { "success" } else { "failure" };
+    metrics::MANIFEST_WRITES
+        .with_label_values(&[backend.name(), outcome])
+        .inc();
+    result
+}
@@ -0,0 +1,142 @@
+use super::{encryption, SegmentInfo};
+use crate::camera::probe;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tracing::warn;
+
+/// Descriptive metadata attached to every uploaded segment, so downstream
+/// tooling can query footage without downloading it
+#[derive(Debug, Clone)]
+pub struct SegmentMetadata {
+    pub camera_id: String,
+    pub camera_name: String,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    pub duration_secs: Option<f64>,
+    pub video_codec: Option<String>,
+    pub resolution: Option<String>,
+    pub size_bytes: u64,
+    pub host: String,
+    pub version: &'static str,
+    /// Master key the uploaded copy is encrypted with, if encryption is on
+    pub encryption_key_id: Option<String>,
+}
+
+impl SegmentMetadata {
+    /// Gather metadata for a segment, probing the file for media details.
+    ///
+    /// Probe failures only drop the media fields; they never block an upload.
+    pub async fn collect(segment: &SegmentInfo) -> Self {
+        let size_bytes = tokio::fs::metadata(&segment.local_path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+
+        let media = match probe::probe_media(&segment.local_path).await {
+            Ok(media) => media,
+            Err(e) => {
+                warn!(
+                    error = %e,
+                    path = %segment.local_path.display(),
+                    "Failed to probe segment, uploading without media metadata"
+                );
+                Default::default()
+            }
+        };
+
+        // Times recorded when the segment completed win over a fresh probe
+        let duration_secs = segment.duration_secs.or(media.duration_secs);
+        let end = segment.end.or_else(|| {
+            duration_secs.and_then(|secs| {
+                chrono::Duration::try_milliseconds((secs * 1000.0) as i64)
+                    .map(|duration| segment.start + duration)
+            })
+        });
+
+        Self {
+            camera_id: segment.camera_id.clone(),
+            camera_name: segment.camera_name.clone(),
+            start: segment.start,
+            end,
+            duration_secs,
+            video_codec: media.video_codec.clone(),
+            resolution: media.resolution(),
+            size_bytes,
+            host: recorder_host().to_string(),
+            version: env!("CARGO_PKG_VERSION"),
+            encryption_key_id: None,
+        }
+    }
+
+    /// S3 user metadata (sent as `x-amz-meta-*` headers)
+    pub fn to_object_metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        metadata.insert("camera-id".to_string(), self.camera_id.clone());
+        metadata.insert("camera-name".to_string(), self.camera_name.clone());
+        metadata.insert(
+            "segment-start".to_string(),
+            self.start.to_rfc3339_opts(SecondsFormat::Millis, true),
+        );
+        if let Some(end) = self.end {
+            metadata.insert(
+                "segment-end".to_string(),
+                end.to_rfc3339_opts(SecondsFormat::Millis, true),
+            );
+        }
+        if let Some(duration) = self.duration_secs {
+            metadata.insert("duration-secs".to_string(), format!("{duration:.3}"));
+        }
+        if let Some(codec) = &self.video_codec {
+            metadata.insert("codec".to_string(), codec.clone());
+        }
+        if let Some(resolution) = &self.resolution {
+            metadata.insert("resolution".to_string(), resolution.clone());
+        }
+        metadata.insert("size-bytes".to_string(), self.size_bytes.to_string());
+        metadata.insert("recorder-host".to_string(), self.host.clone());
+        metadata.insert("recorder-version".to_string(), self.version.to_string());
+        if let Some(key_id) = &self.encryption_key_id {
+            metadata.insert(
+                encryption::ALGORITHM_METADATA_KEY.to_string(),
+                encryption::ALGORITHM.to_string(),
+            );
+            metadata.insert(encryption::KEY_ID_METADATA_KEY.to_string(), key_id.clone());
+        }
+
+        metadata
+            .into_iter()
+            .map(|(k, v)| (k, header_safe(&v)))
+            .collect()
+    }
+}
+
+/// Hostname of the machine (or pod) running the recorder
+pub(crate) fn recorder_host() -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    })
+}
+
+/// Metadata travels in HTTP headers, so percent-encode anything that isn't
+/// printable ASCII (camera names are user-provided)
+fn header_safe(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_graphic() || c == ' ' {
+            out.push(c);
+        } else {
+            let mut buf = [0u8; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                out.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    out
+}
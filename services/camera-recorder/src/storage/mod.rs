@@ -1,5 +1,32 @@
+pub mod backend;
+pub mod checksum;
+pub mod credentials;
+pub mod dead_letter;
+pub mod encryption;
+pub mod janitor;
+pub mod journal;
+pub mod key_template;
+pub mod local;
+pub mod manifest;
+pub mod metadata;
+pub mod multipart;
+pub mod priority;
+pub mod reconcile;
+pub mod retention;
 pub mod s3_client;
+pub mod sse;
+pub mod throttle;
 pub mod uploader;
 
+pub use backend::StorageBackend;
+pub use checksum::{ChecksumMismatch, KeyConflict};
+pub use dead_letter::DeadLetters;
+pub use janitor::LocalJanitor;
+pub use journal::UploadJournal;
+pub use key_template::KeyLayout;
+pub use local::LocalBackend;
+pub use priority::UploadPriorities;
+pub use retention::RemoteRetention;
 pub use s3_client::S3Client;
-pub use uploader::{SegmentInfo, UploadWorker};
+pub use throttle::BandwidthLimiter;
+pub use uploader::{SegmentInfo, UploadQueue, UploadWorker};
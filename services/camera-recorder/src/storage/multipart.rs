@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const SIDECAR_SUFFIX: &str = ".upload.json";
+
+/// S3 requires every part except the last to be at least 5 MiB
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// A part that has been accepted by the bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub e_tag: String,
+}
+
+/// Progress of an in-flight multipart upload, persisted next to the segment
+/// as `<segment>.<destination>.upload.json` so a retry or restart can resume it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartState {
+    pub s3_key: String,
+    pub upload_id: String,
+    pub part_size: u64,
+    pub file_size: u64,
+    /// Hex SHA-256 the upload was created with (stored in object metadata)
+    #[serde(default)]
+    pub sha256: String,
+    pub parts: Vec<UploadedPart>,
+}
+
+impl MultipartState {
+    pub fn new(
+        s3_key: &str,
+        upload_id: &str,
+        part_size: u64,
+        file_size: u64,
+        sha256: &str,
+    ) -> Self {
+        Self {
+            s3_key: s3_key.to_string(),
+            upload_id: upload_id.to_string(),
+            part_size,
+            file_size,
+            sha256: sha256.to_string(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Load saved progress for a segment, if there is any
+    pub async fn load(local_path: &Path, destination: &str) -> Result<Option<Self>> {
+        match tokio::fs::read(sidecar_path(local_path, destination)).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(state) => Ok(Some(state)),
+                Err(e) => {
+                    warn!(error = %e, path = %local_path.display(), "Ignoring corrupt multipart state");
+                    Ok(None)
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read multipart state"),
+        }
+    }
+
+    /// Atomically persist progress
+    pub async fn save(&self, local_path: &Path, destination: &str) -> Result<()> {
+        let path = sidecar_path(local_path, destination);
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .await
+            .context("Failed to write multipart state")?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .context("Failed to replace multipart state")?;
+        Ok(())
+    }
+
+    /// Whether saved progress still applies to this upload
+    pub fn matches(&self, s3_key: &str, part_size: u64, file_size: u64, sha256: &str) -> bool {
+        self.s3_key == s3_key
+            && self.part_size == part_size
+            && self.file_size == file_size
+            && self.sha256 == sha256
+    }
+
+    pub fn has_part(&self, part_number: i32) -> bool {
+        self.parts.iter().any(|p| p.part_number == part_number)
+    }
+}
+
+/// Sidecar file holding multipart progress for a segment and destination
+pub fn sidecar_path(local_path: &Path, destination: &str) -> PathBuf {
+    let mut name = local_path.as_os_str().to_owned();
+    name.push(format!(".{destination}{SIDECAR_SUFFIX}"));
+    PathBuf::from(name)
+}
+
+/// Remove saved progress for a segment and destination, ignoring a missing file
+pub async fn remove_state(local_path: &Path, destination: &str) -> Result<()> {
+    match tokio::fs::remove_file(sidecar_path(local_path, destination)).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).context("Failed to remove multipart state"),
+    }
+}
+
+/// Remove saved progress for a segment across all destinations
+pub async fn remove_all_state(local_path: &Path) -> Result<()> {
+    let (Some(dir), Some(filename)) = (local_path.parent(), local_path.file_name()) else {
+        return Ok(());
+    };
+    let prefix = format!("{}.", filename.to_string_lossy());
+
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read segment directory"),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && name.ends_with(SIDECAR_SUFFIX) {
+            tokio::fs::remove_file(entry.path())
+                .await
+                .context("Failed to remove multipart state")?;
+        }
+    }
+    Ok(())
+}
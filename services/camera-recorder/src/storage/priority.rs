@@ -0,0 +1,244 @@
+use super::SegmentInfo;
+use crate::config::CameraConfig;
+use crate::metrics;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::info;
+
+/// Bookmarks are kept this long so they can still match segments that are
+/// recording, queued behind a backlog or replayed after a restart
+const BOOKMARK_TTL_HOURS: i64 = 24;
+
+/// Upload priority classes, highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum UploadPriority {
+    /// Covers a bookmark (manual or event-triggered)
+    Important,
+    /// The most recent segment of its camera
+    Latest,
+    /// Everything else, oldest first
+    Backlog,
+}
+
+impl UploadPriority {
+    const ALL: [Self; 3] = [Self::Important, Self::Latest, Self::Backlog];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Important => "important",
+            Self::Latest => "latest",
+            Self::Backlog => "backlog",
+        }
+    }
+}
+
+/// A moment worth uploading first, e.g. a motion event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub camera_id: String,
+    /// Defaults to now, i.e. the segment currently recording
+    #[serde(default = "Utc::now")]
+    pub time: DateTime<Utc>,
+    /// Free text, e.g. "manual" or "motion"
+    #[serde(default = "default_bookmark_reason")]
+    pub reason: String,
+}
+
+fn default_bookmark_reason() -> String {
+    "manual".to_string()
+}
+
+#[derive(Default)]
+struct CameraQueue {
+    segments: VecDeque<SegmentInfo>,
+    /// Start of the newest segment this camera has queued
+    newest: Option<DateTime<Utc>>,
+}
+
+struct QueueState {
+    cameras: BTreeMap<String, CameraQueue>,
+    bookmarks: Vec<Bookmark>,
+    /// Camera served last, so cameras take turns
+    last_served: Option<String>,
+    /// Segments popped and not yet finished uploading
+    in_flight: HashSet<PathBuf>,
+}
+
+/// Segments waiting for an upload slot.
+///
+/// Bookmarked segments go first, then each camera's most recent segment,
+/// then the backlog oldest first. Within a class cameras take turns, so one
+/// camera's backlog can't starve another.
+#[derive(Clone)]
+pub struct UploadPriorities {
+    state: Arc<Mutex<QueueState>>,
+    cameras: Arc<HashMap<String, CameraConfig>>,
+    /// Wakes the upload worker for segments that didn't come through its channel
+    pushed: Arc<Notify>,
+}
+
+impl UploadPriorities {
+    pub fn new(cameras: &[CameraConfig]) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(QueueState {
+                cameras: BTreeMap::new(),
+                bookmarks: Vec::new(),
+                last_served: None,
+                in_flight: HashSet::new(),
+            })),
+            cameras: Arc::new(cameras.iter().map(|c| (c.id.clone(), c.clone())).collect()),
+            pushed: Arc::new(Notify::new()),
+        }
+    }
+
+    fn covers(&self, segment: &SegmentInfo, bookmark: &Bookmark) -> bool {
+        if segment.camera_id != bookmark.camera_id {
+            return false;
+        }
+        // Segments still recording (or never probed) last their nominal duration
+        let end = segment.end.unwrap_or_else(|| {
+            let duration = self
+                .cameras
+                .get(&segment.camera_id)
+                .map_or(0, |c| c.segment_duration_secs);
+            segment.start + Duration::seconds(duration as i64)
+        });
+        segment.start <= bookmark.time && bookmark.time < end
+    }
+
+    fn priority_of(
+        &self,
+        state: &QueueState,
+        queue: &CameraQueue,
+        segment: &SegmentInfo,
+    ) -> UploadPriority {
+        if state.bookmarks.iter().any(|b| self.covers(segment, b)) {
+            UploadPriority::Important
+        } else if queue.newest == Some(segment.start) {
+            UploadPriority::Latest
+        } else {
+            UploadPriority::Backlog
+        }
+    }
+
+    /// Queue a segment; segments already queued or uploading are ignored
+    pub fn push(&self, segment: SegmentInfo) {
+        let start = segment.start;
+        let mut state = self.state.lock().unwrap();
+        if state.in_flight.contains(&segment.local_path) {
+            return;
+        }
+        let queue = state.cameras.entry(segment.camera_id.clone()).or_default();
+        if queue
+            .segments
+            .iter()
+            .any(|s| s.local_path == segment.local_path)
+        {
+            return;
+        }
+        queue.newest = queue.newest.max(Some(start));
+        // Keep each camera's queue in recording order
+        let index = queue.segments.partition_point(|s| s.start <= start);
+        queue.segments.insert(index, segment);
+        self.update_depth(&state);
+        self.pushed.notify_one();
+    }
+
+    /// Wait until a segment is pushed
+    pub async fn pushed(&self) {
+        self.pushed.notified().await
+    }
+
+    /// Take the next segment to upload, if any
+    pub fn pop(&self) -> Option<SegmentInfo> {
+        let mut state = self.state.lock().unwrap();
+
+        // Best (priority, index) per camera
+        let mut candidates: Vec<(String, UploadPriority, usize)> = Vec::new();
+        for (camera_id, queue) in &state.cameras {
+            let best = queue
+                .segments
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (self.priority_of(&state, queue, s), i))
+                .min();
+            if let Some((priority, index)) = best {
+                candidates.push((camera_id.clone(), priority, index));
+            }
+        }
+        let top = candidates.iter().map(|(_, p, _)| *p).min()?;
+        candidates.retain(|(_, p, _)| *p == top);
+
+        // First camera after the one served last, wrapping around
+        let (camera_id, _, index) = candidates
+            .iter()
+            .find(|(id, _, _)| state.last_served.as_ref().is_some_and(|last| id > last))
+            .unwrap_or(&candidates[0])
+            .clone();
+
+        let segment = state.cameras.get_mut(&camera_id)?.segments.remove(index)?;
+        state.last_served = Some(camera_id);
+        state.in_flight.insert(segment.local_path.clone());
+        self.update_depth(&state);
+        Some(segment)
+    }
+
+    /// A popped segment is done (uploaded or given up on)
+    pub fn finished(&self, path: &Path) {
+        self.state.lock().unwrap().in_flight.remove(path);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.cameras.values().all(|q| q.segments.is_empty())
+    }
+
+    /// Upload whatever covers this moment ahead of the rest, including a
+    /// segment that is still recording
+    pub fn bookmark(&self, bookmark: Bookmark) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let cutoff = Utc::now() - Duration::hours(BOOKMARK_TTL_HOURS);
+        state.bookmarks.retain(|b| b.time > cutoff);
+
+        let queued = state.cameras.get(&bookmark.camera_id).map_or(0, |q| {
+            q.segments
+                .iter()
+                .filter(|s| self.covers(s, &bookmark))
+                .count()
+        });
+        info!(
+            camera_id = %bookmark.camera_id,
+            time = %bookmark.time,
+            reason = %bookmark.reason,
+            queued,
+            "Segment bookmarked for priority upload"
+        );
+        state.bookmarks.push(bookmark);
+        self.update_depth(&state);
+        queued
+    }
+
+    pub fn has_camera(&self, camera_id: &str) -> bool {
+        self.cameras.contains_key(camera_id)
+    }
+
+    fn update_depth(&self, state: &QueueState) {
+        let mut depth: HashMap<UploadPriority, usize> = HashMap::new();
+        for queue in state.cameras.values() {
+            for segment in &queue.segments {
+                *depth
+                    .entry(self.priority_of(state, queue, segment))
+                    .or_default() += 1;
+            }
+        }
+        for priority in UploadPriority::ALL {
+            metrics::UPLOAD_QUEUE_DEPTH
+                .with_label_values(&[priority.label()])
+                .set(depth.get(&priority).copied().unwrap_or(0) as f64);
+        }
+    }
+}
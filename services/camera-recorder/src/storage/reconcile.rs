@@ -0,0 +1,195 @@
+use super::backend::ObjectInfo;
+use super::checksum::{Sha256Digest, SHA256_METADATA_KEY};
+use super::journal::SegmentState;
+use super::uploader::cleanup_local_file;
+use super::{encryption, KeyLayout, SegmentInfo, StorageBackend, UploadJournal, UploadQueue};
+use crate::config::Config;
+use crate::metrics;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// What the startup reconciliation pass found in temp_dir
+#[derive(Debug, Default)]
+pub struct ReconcileSummary {
+    pub scanned: usize,
+    pub requeued: usize,
+    pub requeued_bytes: u64,
+    pub already_uploaded: usize,
+    pub skipped: usize,
+}
+
+/// Find finished segments left in temp_dir by a previous process and
+/// re-queue the ones that never made it to storage.
+///
+/// Files modified at or after `started_at` belong to this process's FFmpeg
+/// (the currently-open segment) and are left alone, as are files the upload
+/// journal is already tracking. A segment counts as uploaded once as many
+/// destinations as the delete policy requires hold an object matching its size
+/// and SHA-256; otherwise it is re-queued and only the destinations missing it
+/// are uploaded to. With a local retention window the stored segment is
+/// journaled as uploaded and left for the janitor instead of deleted.
+pub async fn reconcile_orphans(
+    config: &Config,
+    destinations: &[Arc<dyn StorageBackend>],
+    keys: &KeyLayout,
+    journal: &UploadJournal,
+    upload_queue: &UploadQueue,
+    started_at: SystemTime,
+) -> Result<ReconcileSummary> {
+    let mut summary = ReconcileSummary::default();
+    let required_successes = config.storage.required_successes();
+
+    for camera in &config.cameras {
+        let camera_dir = config.recording.temp_dir.join(&camera.id);
+        let mut entries = match tokio::fs::read_dir(&camera_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).context("Failed to read camera temp directory"),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(segment) = SegmentInfo::from_file(camera, &path) else {
+                continue;
+            };
+            summary.scanned += 1;
+
+            let metadata = entry.metadata().await?;
+            let is_current = metadata
+                .modified()
+                .map(|modified| modified >= started_at)
+                .unwrap_or(true);
+            if is_current || metadata.len() == 0 || journal.contains(&path) {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let s3_key = keys.key_for(&segment);
+            let mut stored_on = Vec::new();
+            // Only hashed if an object of the same size is found
+            let mut digest = None;
+            for backend in destinations {
+                match backend.head(&s3_key).await {
+                    Ok(Some(existing)) => {
+                        match mismatch(&existing, &path, metadata.len(), &mut digest).await {
+                            Ok(None) => stored_on.push(backend.name()),
+                            Ok(Some(detail)) => warn!(
+                                camera_id = %camera.id,
+                                destination = %backend.name(),
+                                s3_key = %s3_key,
+                                detail = %detail,
+                                "Stored object differs from orphaned segment, treating it as missing"
+                            ),
+                            Err(e) => warn!(
+                                error = %e,
+                                camera_id = %camera.id,
+                                destination = %backend.name(),
+                                s3_key = %s3_key,
+                                "Could not verify orphaned segment, treating it as missing"
+                            ),
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        // Uploading again is harmless, losing the segment is not
+                        warn!(
+                            error = %e,
+                            camera_id = %camera.id,
+                            destination = %backend.name(),
+                            s3_key = %s3_key,
+                            "Could not check orphaned segment, treating it as missing"
+                        );
+                    }
+                }
+            }
+            let exists = stored_on.len() >= required_successes;
+
+            if exists {
+                info!(
+                    camera_id = %camera.id,
+                    s3_key = %s3_key,
+                    "Orphaned segment already in storage"
+                );
+                summary.already_uploaded += 1;
+                metrics::ORPHANED_SEGMENTS
+                    .with_label_values(&[&camera.id, "already_uploaded"])
+                    .inc();
+
+                if config.recording.local_retention_minutes > 0 {
+                    // The janitor expires it once the retention window has passed
+                    for destination in stored_on {
+                        if let Err(e) = journal.record_destination(&segment, destination) {
+                            warn!(error = %e, destination = %destination, "Failed to journal destination upload");
+                        }
+                    }
+                    if let Err(e) = journal.record(&segment, SegmentState::Uploaded) {
+                        warn!(error = %e, path = %path.display(), "Failed to journal upload");
+                    }
+                } else if let Err(e) = cleanup_local_file(&path).await {
+                    warn!(error = %e, path = %path.display(), "Failed to cleanup local file");
+                }
+            } else {
+                info!(
+                    camera_id = %camera.id,
+                    s3_key = %s3_key,
+                    size_mb = metadata.len() / 1_048_576,
+                    "Re-queuing orphaned segment"
+                );
+                for destination in stored_on {
+                    if let Err(e) = journal.record_destination(&segment, destination) {
+                        warn!(error = %e, destination = %destination, "Failed to journal destination upload");
+                    }
+                }
+                upload_queue.enqueue(segment).await?;
+                summary.requeued += 1;
+                summary.requeued_bytes += metadata.len();
+                metrics::ORPHANED_SEGMENTS
+                    .with_label_values(&[&camera.id, "requeued"])
+                    .inc();
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Why a stored object is not a copy of the local segment, or `None` if it is.
+///
+/// Encrypted objects carry the SHA-256 of their ciphertext, which a fresh
+/// encryption never reproduces, so only their recorded plaintext size is compared.
+async fn mismatch(
+    existing: &ObjectInfo,
+    path: &Path,
+    size: u64,
+    digest: &mut Option<Sha256Digest>,
+) -> Result<Option<String>> {
+    if existing
+        .metadata
+        .contains_key(encryption::KEY_ID_METADATA_KEY)
+    {
+        return Ok(match existing.metadata.get("size-bytes") {
+            Some(stored) if *stored == size.to_string() => None,
+            Some(stored) => Some(format!("size {stored} != {size}")),
+            None => Some("no size metadata to compare".to_string()),
+        });
+    }
+    if existing.size != size {
+        return Ok(Some(format!("size {} != {size}", existing.size)));
+    }
+
+    if digest.is_none() {
+        *digest = Some(Sha256Digest::of_file(path).await?);
+    }
+    let local = digest
+        .as_ref()
+        .map(Sha256Digest::to_hex)
+        .unwrap_or_default();
+    Ok(match existing.metadata.get(SHA256_METADATA_KEY) {
+        Some(remote) if *remote == local => None,
+        Some(remote) => Some(format!("sha256 {remote} != {local}")),
+        None => Some("no sha256 metadata to compare".to_string()),
+    })
+}
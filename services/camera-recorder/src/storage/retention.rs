@@ -0,0 +1,190 @@
+use super::backend::ObjectInfo;
+use super::manifest;
+use super::{KeyLayout, StorageBackend};
+use crate::config::{CameraConfig, RetentionConfig};
+use crate::metrics;
+use anyhow::Result;
+use chrono::{NaiveDate, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tokio::time::{interval, Duration};
+use tracing::{info, warn};
+
+/// Marker object that protects recordings from retention: `<key>.hold` pins a
+/// single segment, `<dir>/.hold` (e.g. `camera-1/20251214/.hold`) pins
+/// everything below that directory
+pub const HOLD_SUFFIX: &str = ".hold";
+
+/// Deletes days of recordings older than each camera's retention window from
+/// every storage destination.
+pub struct RemoteRetention {
+    destinations: Vec<Arc<dyn StorageBackend>>,
+    /// (camera_id, retention_days) for cameras that have a retention window
+    cameras: Vec<(String, u32)>,
+    keys: Arc<KeyLayout>,
+    dry_run: bool,
+    interval: Duration,
+}
+
+/// What one pass over a camera's prefix deleted (or would have, in dry-run)
+#[derive(Debug, Default)]
+struct SweepSummary {
+    days: usize,
+    objects: usize,
+    bytes: u64,
+    held: usize,
+}
+
+impl RemoteRetention {
+    pub fn new(
+        config: &RetentionConfig,
+        cameras: &[CameraConfig],
+        destinations: Vec<Arc<dyn StorageBackend>>,
+        keys: Arc<KeyLayout>,
+    ) -> Self {
+        Self {
+            destinations,
+            cameras: cameras
+                .iter()
+                .filter_map(|c| {
+                    c.retention_days
+                        .or(config.default_days)
+                        .map(|days| (c.id.clone(), days))
+                })
+                .collect(),
+            keys,
+            dry_run: config.dry_run,
+            interval: Duration::from_secs(config.interval_minutes * 60),
+        }
+    }
+
+    /// Run the retention loop forever
+    pub async fn run(self) {
+        if self.cameras.is_empty() {
+            info!("Remote retention disabled (no retention_days configured)");
+            return;
+        }
+
+        info!(
+            cameras = self.cameras.len(),
+            dry_run = self.dry_run,
+            interval_mins = self.interval.as_secs() / 60,
+            "Remote retention started"
+        );
+
+        let mut ticker = interval(self.interval);
+        loop {
+            ticker.tick().await;
+
+            for backend in &self.destinations {
+                for (camera_id, days) in &self.cameras {
+                    if let Err(e) = self.sweep(backend.as_ref(), camera_id, *days).await {
+                        warn!(
+                            error = %e,
+                            camera_id = %camera_id,
+                            destination = %backend.name(),
+                            "Remote retention sweep failed"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Delete the camera's objects dated more than `days` ago, skipping held ones
+    async fn sweep(&self, backend: &dyn StorageBackend, camera_id: &str, days: u32) -> Result<()> {
+        let Some(template) = self.keys.camera(camera_id) else {
+            return Ok(());
+        };
+        let cutoff = Utc::now().date_naive() - chrono::Days::new(u64::from(days));
+        let objects = backend.list(template.list_prefix()).await?;
+
+        let holds: HashSet<&str> = objects
+            .iter()
+            .filter_map(|o| o.key.strip_suffix(HOLD_SUFFIX))
+            .collect();
+        let is_held = |key: &str| {
+            holds.contains(key) || holds.iter().any(|h| h.ends_with('/') && key.starts_with(h))
+        };
+
+        let mut by_day: BTreeMap<NaiveDate, Vec<&ObjectInfo>> = BTreeMap::new();
+        for object in &objects {
+            if object.key.ends_with(HOLD_SUFFIX) || !template.matches(&object.key) {
+                continue;
+            }
+            // Templates without a date fall back to when the object was stored
+            let day = template
+                .date_of(&object.key)
+                .or_else(|| object.last_modified.map(|t| t.date_naive()));
+            if let Some(day) = day {
+                by_day.entry(day).or_default().push(object);
+            }
+        }
+
+        let mut summary = SweepSummary::default();
+        let mut held_days = HashSet::new();
+        for (day, objects) in by_day.range(..cutoff) {
+            summary.days += 1;
+            for object in objects {
+                if is_held(&object.key) {
+                    summary.held += 1;
+                    held_days.insert(*day);
+                    continue;
+                }
+
+                if self.dry_run {
+                    info!(
+                        key = %object.key,
+                        destination = %backend.name(),
+                        size_bytes = object.size,
+                        "Retention dry-run: would delete"
+                    );
+                } else {
+                    backend.delete(&object.key).await?;
+                    metrics::RETENTION_DELETED_OBJECTS
+                        .with_label_values(&[camera_id, backend.name()])
+                        .inc();
+                    metrics::RETENTION_DELETED_BYTES
+                        .with_label_values(&[camera_id, backend.name()])
+                        .inc_by(object.size as f64);
+                }
+                summary.objects += 1;
+                summary.bytes += object.size;
+            }
+        }
+
+        // A day's manifest goes with its last segment
+        for object in backend.list(&manifest::camera_prefix(camera_id)).await? {
+            let Some(day) = manifest::date_of(&object.key) else {
+                continue;
+            };
+            if day >= cutoff || held_days.contains(&day) {
+                continue;
+            }
+            if self.dry_run {
+                info!(
+                    key = %object.key,
+                    destination = %backend.name(),
+                    "Retention dry-run: would delete manifest"
+                );
+            } else {
+                backend.delete(&object.key).await?;
+            }
+        }
+
+        if summary.objects > 0 || summary.held > 0 {
+            info!(
+                camera_id = %camera_id,
+                destination = %backend.name(),
+                retention_days = days,
+                days = summary.days,
+                objects = summary.objects,
+                deleted_mb = summary.bytes / 1_048_576,
+                held = summary.held,
+                dry_run = self.dry_run,
+                "Remote retention sweep complete"
+            );
+        }
+        Ok(())
+    }
+}
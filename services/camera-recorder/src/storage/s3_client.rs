@@ -1,44 +1,61 @@
-use crate::config::StorageConfig;
+use super::backend::{ObjectInfo, StorageBackend};
+use super::checksum::{ChecksumMismatch, Sha256Digest, SHA256_METADATA_KEY};
+use super::credentials;
+use super::metadata::SegmentMetadata;
+use super::multipart::{self, MultipartState, UploadedPart};
+use super::sse::SseSettings;
+use super::throttle::UploadThrottle;
+use crate::config::{DestinationConfig, SseMode, UploadConfig};
 use anyhow::{Context, Result};
-use aws_credential_types::Credentials;
-use aws_sdk_s3::config::{BehaviorVersion, Region, SharedCredentialsProvider};
+use async_trait::async_trait;
+use aws_sdk_s3::config::{BehaviorVersion, Region};
 use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{ChecksumMode, ChecksumType, CompletedMultipartUpload, CompletedPart};
 use aws_sdk_s3::Client;
+use aws_smithy_types::body::SdkBody;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
-use tracing::info;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Object written at startup to check server-side encryption support
+const SSE_PROBE_KEY: &str = ".camera-recorder-sse-probe";
 
 #[derive(Clone)]
 pub struct S3Client {
+    name: String,
     client: Client,
     bucket: String,
+    multipart_threshold_bytes: u64,
+    multipart_part_size_bytes: u64,
+    sse: SseSettings,
 }
 
 impl S3Client {
     /// Create new S3 client for SeaweedFS
-    pub async fn new(config: &StorageConfig) -> Result<Self> {
-        // Create credentials
-        let credentials = Credentials::new(
-            &config.access_key_id,
-            &config.secret_access_key,
-            None,
-            None,
-            "static",
-        );
+    pub async fn new(config: &DestinationConfig, upload: &UploadConfig) -> Result<Self> {
+        let credentials = credentials::provider(config).await?;
 
         // Build S3 config with path-style addressing for SeaweedFS compatibility
         let s3_config = aws_sdk_s3::Config::builder()
             .behavior_version(BehaviorVersion::latest())
             .region(Region::new(config.region.clone()))
             .endpoint_url(&config.endpoint)
-            .credentials_provider(SharedCredentialsProvider::new(credentials))
-            .force_path_style(true)  // Required for SeaweedFS and other S3-compatible stores
+            .credentials_provider(credentials)
+            .force_path_style(true) // Required for SeaweedFS and other S3-compatible stores
             .build();
 
         let client = Client::from_conf(s3_config);
 
         Ok(Self {
+            name: config.name.clone(),
             client,
             bucket: config.bucket.clone(),
+            multipart_threshold_bytes: upload.multipart_threshold_bytes,
+            multipart_part_size_bytes: upload.multipart_part_size_bytes,
+            sse: SseSettings::from_config(config)?,
         })
     }
 
@@ -63,37 +80,558 @@ impl S3Client {
         }
     }
 
-    /// Upload a file to S3
-    pub async fn upload_file(&self, local_path: &Path, s3_key: &str) -> Result<()> {
+    /// Write, HEAD and delete a probe object to prove the endpoint honours
+    /// the configured server-side encryption
+    async fn verify_sse_support(&self) -> Result<()> {
+        let mode = self.sse.mode();
+        if mode == SseMode::None {
+            return Ok(());
+        }
+
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(SSE_PROBE_KEY)
+            .body(ByteStream::from_static(b"sse probe"))
+            .set_server_side_encryption(self.sse.algorithm())
+            .set_ssekms_key_id(self.sse.kms_key_id())
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
+            .await
+            .with_context(|| format!("Endpoint rejected {mode} for bucket {}", self.bucket))?;
+
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(SSE_PROBE_KEY)
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
+            .await
+            .with_context(|| format!("Failed to read back {mode} probe object"))?;
+        let verified = self.sse.check_head(SSE_PROBE_KEY, &head);
+
+        if let Err(e) = self
+            .client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(SSE_PROBE_KEY)
+            .send()
+            .await
+        {
+            warn!(error = %e, "Failed to delete server-side encryption probe object");
+        }
+
+        verified.with_context(|| format!("Endpoint does not support {mode}"))?;
+        info!(bucket = %self.bucket, sse = %mode, "Server-side encryption verified");
+        Ok(())
+    }
+
+    /// Upload a file to S3, using a resumable multipart upload for large files.
+    ///
+    /// The object is verified against the file's SHA-256 and size before this
+    /// returns `Ok`, so the local copy is safe to delete afterwards.
+    pub async fn upload_file(
+        &self,
+        local_path: &Path,
+        s3_key: &str,
+        metadata: &SegmentMetadata,
+        throttle: &UploadThrottle,
+    ) -> Result<()> {
         info!(
             local_path = %local_path.display(),
             s3_key = %s3_key,
             "Uploading file to SeaweedFS"
         );
 
-        let body = ByteStream::from_path(local_path)
+        let file_size = tokio::fs::metadata(local_path)
+            .await
+            .context("Failed to stat file")?
+            .len();
+        let digest = Sha256Digest::of_file(local_path).await?;
+
+        let mut object_metadata = metadata.to_object_metadata();
+        object_metadata.insert(SHA256_METADATA_KEY.to_string(), digest.to_hex());
+
+        if file_size >= self.multipart_threshold_bytes {
+            self.upload_multipart(
+                local_path,
+                s3_key,
+                file_size,
+                &digest,
+                object_metadata,
+                throttle,
+            )
+            .await?;
+        } else {
+            // Outside the upload windows wait here, before the request goes out
+            throttle.wait_for_window().await;
+            let body = throttled_body(throttle, local_path, 0, file_size);
+
+            // The checksum header makes the server reject corrupted bodies outright
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(s3_key)
+                .body(body)
+                .content_length(file_size as i64)
+                .checksum_sha256(digest.to_base64())
+                .set_metadata(Some(object_metadata))
+                .set_server_side_encryption(self.sse.algorithm())
+                .set_ssekms_key_id(self.sse.kms_key_id())
+                .set_sse_customer_algorithm(self.sse.customer_algorithm())
+                .set_sse_customer_key(self.sse.customer_key())
+                .set_sse_customer_key_md5(self.sse.customer_key_md5())
+                .send()
+                .await
+                .context("Failed to upload to S3")?;
+        }
+
+        self.verify_upload(s3_key, file_size, &digest).await?;
+
+        info!(s3_key = %s3_key, sha256 = %digest.to_hex(), "Upload successful");
+        Ok(())
+    }
+
+    /// HEAD the object and compare size and SHA-256 with the local file
+    async fn verify_upload(
+        &self,
+        s3_key: &str,
+        file_size: u64,
+        digest: &Sha256Digest,
+    ) -> Result<()> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(s3_key)
+            .checksum_mode(ChecksumMode::Enabled)
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
             .await
-            .context("Failed to read file")?;
+            .context("Failed to verify uploaded object")?;
+
+        self.sse.check_head(s3_key, &head)?;
+
+        let mismatch = |detail: String| ChecksumMismatch {
+            key: s3_key.to_string(),
+            detail,
+        };
+
+        let remote_size = head.content_length().unwrap_or(-1);
+        if remote_size != file_size as i64 {
+            return Err(mismatch(format!("size {remote_size} != {file_size}")).into());
+        }
+
+        let remote_sha256 = head
+            .metadata()
+            .and_then(|m| m.get(SHA256_METADATA_KEY))
+            .map(String::as_str);
+        if remote_sha256 != Some(digest.to_hex().as_str()) {
+            return Err(mismatch(format!(
+                "sha256 metadata {remote_sha256:?} != {}",
+                digest.to_hex()
+            ))
+            .into());
+        }
+
+        // Only a full-object checksum is comparable; multipart uploads get a
+        // composite checksum of their parts
+        if let Some(remote_checksum) = head.checksum_sha256() {
+            let full_object = !matches!(head.checksum_type(), Some(ChecksumType::Composite))
+                && !remote_checksum.contains('-');
+            if full_object && remote_checksum != digest.to_base64() {
+                return Err(mismatch(format!(
+                    "checksum {remote_checksum} != {}",
+                    digest.to_base64()
+                ))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Upload a file in parts, resuming from saved progress when possible
+    async fn upload_multipart(
+        &self,
+        local_path: &Path,
+        s3_key: &str,
+        file_size: u64,
+        digest: &Sha256Digest,
+        object_metadata: HashMap<String, String>,
+        throttle: &UploadThrottle,
+    ) -> Result<()> {
+        let part_size = self.multipart_part_size_bytes;
+
+        let saved = match MultipartState::load(local_path, &self.name).await? {
+            Some(state) if state.matches(s3_key, part_size, file_size, &digest.to_hex()) => {
+                if self.multipart_upload_exists(&state).await {
+                    info!(
+                        s3_key = %s3_key,
+                        upload_id = %state.upload_id,
+                        parts_done = state.parts.len(),
+                        "Resuming multipart upload"
+                    );
+                    Some(state)
+                } else {
+                    warn!(s3_key = %s3_key, "Saved multipart upload no longer exists, restarting");
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        let mut state = match saved {
+            Some(state) => state,
+            None => {
+                let created = self
+                    .client
+                    .create_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(s3_key)
+                    .set_metadata(Some(object_metadata))
+                    .set_server_side_encryption(self.sse.algorithm())
+                    .set_ssekms_key_id(self.sse.kms_key_id())
+                    .set_sse_customer_algorithm(self.sse.customer_algorithm())
+                    .set_sse_customer_key(self.sse.customer_key())
+                    .set_sse_customer_key_md5(self.sse.customer_key_md5())
+                    .send()
+                    .await
+                    .context("Failed to create multipart upload")?;
+                let upload_id = created
+                    .upload_id()
+                    .context("Multipart upload response missing upload id")?;
+                let state =
+                    MultipartState::new(s3_key, upload_id, part_size, file_size, &digest.to_hex());
+                state.save(local_path, &self.name).await?;
+                state
+            }
+        };
+
+        let part_count = file_size.div_ceil(part_size).max(1);
+        for index in 0..part_count {
+            let part_number = index as i32 + 1;
+            if state.has_part(part_number) {
+                continue;
+            }
+
+            let offset = index * part_size;
+            let length = part_size.min(file_size - offset);
+            // A window may close between parts; the next one waits for it to reopen
+            throttle.wait_for_window().await;
+            let body = throttled_body(throttle, local_path, offset, length);
+
+            let uploaded = self
+                .client
+                .upload_part()
+                .bucket(&self.bucket)
+                .key(s3_key)
+                .upload_id(&state.upload_id)
+                .part_number(part_number)
+                .body(body)
+                .content_length(length as i64)
+                .set_sse_customer_algorithm(self.sse.customer_algorithm())
+                .set_sse_customer_key(self.sse.customer_key())
+                .set_sse_customer_key_md5(self.sse.customer_key_md5())
+                .send()
+                .await
+                .with_context(|| format!("Failed to upload part {part_number}/{part_count}"))?;
+
+            state.parts.push(UploadedPart {
+                part_number,
+                e_tag: uploaded.e_tag().unwrap_or_default().to_string(),
+            });
+            state.save(local_path, &self.name).await?;
+        }
+
+        state.parts.sort_by_key(|p| p.part_number);
+        let completed = CompletedMultipartUpload::builder()
+            .set_parts(Some(
+                state
+                    .parts
+                    .iter()
+                    .map(|p| {
+                        CompletedPart::builder()
+                            .part_number(p.part_number)
+                            .e_tag(&p.e_tag)
+                            .build()
+                    })
+                    .collect(),
+            ))
+            .build();
 
         self.client
-            .put_object()
+            .complete_multipart_upload()
             .bucket(&self.bucket)
             .key(s3_key)
-            .body(body)
+            .upload_id(&state.upload_id)
+            .multipart_upload(completed)
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
+            .await
+            .context("Failed to complete multipart upload")?;
+
+        multipart::remove_state(local_path, &self.name).await?;
+
+        info!(s3_key = %s3_key, parts = part_count, "Multipart upload successful");
+        Ok(())
+    }
+
+    /// Whether the bucket still knows about a saved multipart upload
+    async fn multipart_upload_exists(&self, state: &MultipartState) -> bool {
+        self.client
+            .list_parts()
+            .bucket(&self.bucket)
+            .key(&state.s3_key)
+            .upload_id(&state.upload_id)
+            .max_parts(1)
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
             .send()
             .await
-            .context("Failed to upload to S3")?;
+            .is_ok()
+    }
+
+    /// Abort multipart uploads that were started more than `max_age` ago
+    pub async fn abort_stale_multipart_uploads(&self, max_age: Duration) -> Result<usize> {
+        let cutoff = SystemTime::now() - max_age;
+        let mut aborted = 0;
+        let mut key_marker: Option<String> = None;
+        let mut upload_id_marker: Option<String> = None;
+
+        loop {
+            let page = self
+                .client
+                .list_multipart_uploads()
+                .bucket(&self.bucket)
+                .set_key_marker(key_marker.take())
+                .set_upload_id_marker(upload_id_marker.take())
+                .send()
+                .await
+                .context("Failed to list multipart uploads")?;
+
+            for upload in page.uploads() {
+                let (Some(key), Some(upload_id), Some(initiated)) =
+                    (upload.key(), upload.upload_id(), upload.initiated())
+                else {
+                    continue;
+                };
+                let is_stale = SystemTime::try_from(*initiated)
+                    .map(|initiated| initiated < cutoff)
+                    .unwrap_or(false);
+                if !is_stale {
+                    continue;
+                }
+
+                match self
+                    .client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                {
+                    Ok(_) => {
+                        info!(s3_key = %key, upload_id = %upload_id, "Aborted stale multipart upload");
+                        aborted += 1;
+                    }
+                    Err(e) => {
+                        warn!(error = %e, s3_key = %key, "Failed to abort stale multipart upload");
+                    }
+                }
+            }
+
+            if !page.is_truncated().unwrap_or(false) {
+                break;
+            }
+            key_marker = page.next_key_marker().map(str::to_string);
+            upload_id_marker = page.next_upload_id_marker().map(str::to_string);
+            if key_marker.is_none() && upload_id_marker.is_none() {
+                break;
+            }
+        }
 
-        info!(s3_key = %s3_key, "Upload successful");
+        Ok(aborted)
+    }
+}
+
+/// Request body reading part of a file through the upload throttle. The SDK
+/// may retry the request, which re-reads the range from the start.
+fn throttled_body(throttle: &UploadThrottle, path: &Path, offset: u64, length: u64) -> ByteStream {
+    let throttle = throttle.clone();
+    let path = path.to_path_buf();
+    ByteStream::new(SdkBody::retryable(move || {
+        SdkBody::from_body_1_x(throttle.body(&path, offset, length))
+    }))
+}
+
+#[async_trait]
+impl StorageBackend for S3Client {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn describe(&self) -> String {
+        match self.sse.mode() {
+            SseMode::None => format!("s3 bucket {}", self.bucket),
+            mode => format!("s3 bucket {} ({mode})", self.bucket),
+        }
+    }
+
+    async fn ensure_ready(&self) -> Result<()> {
+        self.ensure_bucket_exists().await?;
+        self.verify_sse_support().await
+    }
+
+    async fn put(
+        &self,
+        local_path: &Path,
+        key: &str,
+        metadata: &SegmentMetadata,
+        throttle: &UploadThrottle,
+    ) -> Result<()> {
+        self.upload_file(local_path, key, metadata, throttle).await
+    }
+
+    async fn put_bytes(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(body))
+            .set_server_side_encryption(self.sse.algorithm())
+            .set_ssekms_key_id(self.sse.kms_key_id())
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
+            .await
+            .context("Failed to write object to S3")?;
         Ok(())
     }
 
-    /// Delete local file after successful upload
-    pub async fn cleanup_local_file(&self, path: &Path) -> Result<()> {
-        tokio::fs::remove_file(path)
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let object = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
+            .await
+        {
+            Ok(object) => object,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read object from S3"),
+        };
+        let bytes = object
+            .body
+            .collect()
+            .await
+            .context("Failed to read object body")?;
+        Ok(Some(bytes.to_vec()))
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectInfo>> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
+            .await
+        {
+            Ok(head) => Ok(Some(ObjectInfo {
+                key: key.to_string(),
+                size: head.content_length().unwrap_or(0).max(0) as u64,
+                last_modified: head.last_modified().and_then(to_chrono),
+                metadata: head.metadata().cloned().unwrap_or_default(),
+            })),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(None),
+            Err(e) => Err(e).context("Failed to check object in S3"),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let mut objects = Vec::new();
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.context("Failed to list objects in S3")?;
+            for object in page.contents() {
+                let Some(key) = object.key() else { continue };
+                objects.push(ObjectInfo {
+                    key: key.to_string(),
+                    size: object.size().unwrap_or(0).max(0) as u64,
+                    last_modified: object.last_modified().and_then(to_chrono),
+                    metadata: HashMap::new(),
+                });
+            }
+        }
+        Ok(objects)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
             .await
-            .context("Failed to delete local file")?;
-        info!(path = %path.display(), "Cleaned up local file");
+            .context("Failed to delete object from S3")?;
         Ok(())
     }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        anyhow::ensure!(range.start < range.end, "Empty byte range");
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .range(format!("bytes={}-{}", range.start, range.end - 1))
+            .set_sse_customer_algorithm(self.sse.customer_algorithm())
+            .set_sse_customer_key(self.sse.customer_key())
+            .set_sse_customer_key_md5(self.sse.customer_key_md5())
+            .send()
+            .await
+            .context("Failed to read object from S3")?;
+        let bytes = object
+            .body
+            .collect()
+            .await
+            .context("Failed to read object body")?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn abort_stale_uploads(&self, max_age: Duration) -> Result<usize> {
+        self.abort_stale_multipart_uploads(max_age).await
+    }
+}
+
+fn to_chrono(time: &aws_sdk_s3::primitives::DateTime) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(time.secs(), time.subsec_nanos())
 }
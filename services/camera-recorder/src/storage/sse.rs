@@ -0,0 +1,116 @@
+use super::encryption::decode_key;
+use crate::config::{DestinationConfig, SseMode};
+use anyhow::{Context, Result};
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use aws_sdk_s3::types::ServerSideEncryption;
+use base64::Engine;
+use md5::{Digest, Md5};
+
+/// SSE-C only supports AES256
+const CUSTOMER_ALGORITHM: &str = "AES256";
+
+/// Server-side encryption parameters for an S3 destination.
+///
+/// SSE-S3 and SSE-KMS only apply to writes; SSE-C keys must accompany every
+/// request that touches object data, including HEAD and ranged GETs.
+#[derive(Clone, Default)]
+pub struct SseSettings {
+    mode: SseMode,
+    kms_key_id: Option<String>,
+    customer_key: Option<String>,
+    customer_key_md5: Option<String>,
+}
+
+impl SseSettings {
+    pub fn from_config(config: &DestinationConfig) -> Result<Self> {
+        let mut settings = Self {
+            mode: config.sse,
+            kms_key_id: config.sse_kms_key_id.clone(),
+            ..Default::default()
+        };
+
+        if config.sse == SseMode::SseC {
+            let path = config
+                .sse_customer_key_file
+                .as_ref()
+                .context("sse_customer_key_file is required for SSE-C")?;
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read SSE-C key {}", path.display()))?;
+            let key = decode_key(&text).context("Invalid SSE-C key")?;
+            let engine = base64::engine::general_purpose::STANDARD;
+            settings.customer_key = Some(engine.encode(key));
+            settings.customer_key_md5 = Some(engine.encode(Md5::digest(key)));
+        }
+
+        Ok(settings)
+    }
+
+    pub fn mode(&self) -> SseMode {
+        self.mode
+    }
+
+    /// `x-amz-server-side-encryption` for writes
+    pub fn algorithm(&self) -> Option<ServerSideEncryption> {
+        match self.mode {
+            SseMode::SseS3 => Some(ServerSideEncryption::Aes256),
+            SseMode::SseKms => Some(ServerSideEncryption::AwsKms),
+            SseMode::None | SseMode::SseC => None,
+        }
+    }
+
+    pub fn kms_key_id(&self) -> Option<String> {
+        match self.mode {
+            SseMode::SseKms => self.kms_key_id.clone(),
+            _ => None,
+        }
+    }
+
+    pub fn customer_algorithm(&self) -> Option<String> {
+        self.customer_key
+            .as_ref()
+            .map(|_| CUSTOMER_ALGORITHM.to_string())
+    }
+
+    pub fn customer_key(&self) -> Option<String> {
+        self.customer_key.clone()
+    }
+
+    pub fn customer_key_md5(&self) -> Option<String> {
+        self.customer_key_md5.clone()
+    }
+
+    /// Check that the object was stored with the requested encryption; an
+    /// endpoint that silently ignores SSE headers fails here
+    pub fn check_head(&self, key: &str, head: &HeadObjectOutput) -> Result<()> {
+        match self.mode {
+            SseMode::None => {}
+            SseMode::SseS3 | SseMode::SseKms => {
+                let expected = self.algorithm();
+                anyhow::ensure!(
+                    head.server_side_encryption() == expected.as_ref(),
+                    "Object {key} stored with encryption {:?}, expected {:?}",
+                    head.server_side_encryption(),
+                    expected
+                );
+                // The response carries the key ARN, which can't be matched against an alias
+                let configured = self
+                    .kms_key_id
+                    .as_ref()
+                    .filter(|id| !id.starts_with("alias/"));
+                if let (Some(expected), Some(actual)) = (configured, head.ssekms_key_id()) {
+                    anyhow::ensure!(
+                        actual.contains(expected.as_str()),
+                        "Object {key} encrypted with KMS key {actual}, expected {expected}"
+                    );
+                }
+            }
+            SseMode::SseC => {
+                anyhow::ensure!(
+                    head.sse_customer_algorithm() == Some(CUSTOMER_ALGORITHM),
+                    "Object {key} was not stored with SSE-C"
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+use crate::config::{CameraConfig, UploadConfig, UploadWindow};
+use crate::metrics;
+use bytes::Bytes;
+use chrono::{Local, NaiveTime};
+use futures::stream::{self, Stream, StreamExt};
+use http_body::{Body, Frame, SizeHint};
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::time::{interval, sleep, Duration, Instant};
+use tracing::info;
+
+/// Bytes read (and paid for) at a time while streaming an upload; slower
+/// limits read one second's worth at a time instead
+const CHUNK_SIZE: u64 = 64 * 1024;
+/// How often a paused upload re-checks the schedule
+const PAUSE_POLL: Duration = Duration::from_secs(30);
+
+/// Upload bandwidth allowed right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Unlimited,
+    BytesPerSec(u64),
+    /// Outside the upload windows; uploads wait
+    Paused,
+}
+
+impl Limit {
+    fn from_kbps(kbps: Option<u64>) -> Self {
+        match kbps {
+            None => Self::Unlimited,
+            Some(0) => Self::Paused,
+            Some(kbps) => Self::BytesPerSec(kbps * 1000 / 8),
+        }
+    }
+}
+
+/// Token bucket shared by every upload it applies to. Callers may overdraw
+/// it and then sleep off the debt, so large chunks never starve.
+struct TokenBucket {
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new((0.0, Instant::now())),
+        }
+    }
+
+    /// Take `bytes` at `rate` bytes/s, returning how long to wait for them
+    fn take(&self, bytes: u64, rate: u64) -> Duration {
+        let rate = rate.max(1) as f64;
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        // Allow at most one second of burst after an idle period
+        let tokens = (state.0 + now.duration_since(state.1).as_secs_f64() * rate).min(rate);
+        let tokens = tokens - bytes as f64;
+        *state = (tokens, now);
+        if tokens < 0.0 {
+            Duration::from_secs_f64(-tokens / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Global and per-camera upload bandwidth limits, with time-of-day windows
+pub struct BandwidthLimiter {
+    default_kbps: Option<u64>,
+    schedule: Vec<UploadWindow>,
+    global: TokenBucket,
+    cameras: HashMap<String, (u64, TokenBucket)>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(upload: &UploadConfig, cameras: &[CameraConfig]) -> Self {
+        Self {
+            default_kbps: upload.max_bandwidth_kbps,
+            schedule: upload.schedule.clone(),
+            global: TokenBucket::new(),
+            cameras: cameras
+                .iter()
+                .filter_map(|c| {
+                    let kbps = c.max_upload_kbps?;
+                    Some((c.id.clone(), (kbps * 1000 / 8, TokenBucket::new())))
+                })
+                .collect(),
+        }
+    }
+
+    /// Global limit from the first window containing the current local time,
+    /// or `max_bandwidth_kbps` outside every window
+    pub fn current_limit(&self) -> Limit {
+        let now = Local::now().time();
+        let kbps = self
+            .schedule
+            .iter()
+            .find(|w| window_contains(w, now))
+            .map_or(self.default_kbps, |w| w.max_bandwidth_kbps);
+        Limit::from_kbps(kbps)
+    }
+
+    pub fn throttle(self: &Arc<Self>, camera_id: &str) -> UploadThrottle {
+        UploadThrottle {
+            limiter: Some(self.clone()),
+            camera_id: camera_id.to_string(),
+        }
+    }
+
+    /// Publish the throttle state as metrics and log schedule changes
+    pub async fn run(self: Arc<Self>) {
+        for (camera_id, (rate, _)) in &self.cameras {
+            metrics::UPLOAD_BANDWIDTH_LIMIT
+                .with_label_values(&[camera_id])
+                .set(*rate as f64);
+        }
+
+        let mut last = None;
+        let mut ticker = interval(PAUSE_POLL);
+        loop {
+            ticker.tick().await;
+
+            let limit = self.current_limit();
+            let (rate, paused) = match limit {
+                Limit::Unlimited => (0.0, false),
+                Limit::BytesPerSec(rate) => (rate as f64, false),
+                Limit::Paused => (0.0, true),
+            };
+            metrics::UPLOAD_BANDWIDTH_LIMIT
+                .with_label_values(&["global"])
+                .set(rate);
+            metrics::UPLOADS_PAUSED.set(if paused { 1.0 } else { 0.0 });
+
+            if last != Some(limit) {
+                info!(limit = ?limit, "Upload bandwidth limit changed");
+                last = Some(limit);
+            }
+        }
+    }
+}
+
+fn window_contains(window: &UploadWindow, time: NaiveTime) -> bool {
+    if window.start <= window.end {
+        window.start <= time && time < window.end
+    } else {
+        // Wraps past midnight, e.g. 22:00-06:00
+        time >= window.start || time < window.end
+    }
+}
+
+/// Bandwidth accounting for one camera's uploads
+#[derive(Clone)]
+pub struct UploadThrottle {
+    limiter: Option<Arc<BandwidthLimiter>>,
+    camera_id: String,
+}
+
+impl UploadThrottle {
+    /// Wait until uploads are allowed by the schedule
+    pub async fn wait_for_window(&self) {
+        let Some(limiter) = &self.limiter else {
+            return;
+        };
+        while limiter.current_limit() == Limit::Paused {
+            sleep(PAUSE_POLL).await;
+            self.waited(PAUSE_POLL);
+        }
+    }
+
+    /// Pay for `bytes` about to be sent, sleeping as long as the limits require.
+    ///
+    /// The schedule is not waited on here: backends call `wait_for_window`
+    /// before each request, so an object or part already being sent when a
+    /// window closes finishes without the global cap.
+    pub async fn acquire(&self, bytes: u64) {
+        let Some(limiter) = &self.limiter else {
+            return;
+        };
+
+        let mut wait = Duration::ZERO;
+        if let Limit::BytesPerSec(rate) = limiter.current_limit() {
+            wait = wait.max(limiter.global.take(bytes, rate));
+        }
+        if let Some((rate, bucket)) = limiter.cameras.get(&self.camera_id) {
+            wait = wait.max(bucket.take(bytes, *rate));
+        }
+        if !wait.is_zero() {
+            sleep(wait).await;
+            self.waited(wait);
+        }
+    }
+
+    /// Chunk size worth at most one second at the tightest limit, so no chunk
+    /// sleeps long enough to look like a stalled request
+    fn chunk_size(&self) -> u64 {
+        let Some(limiter) = &self.limiter else {
+            return CHUNK_SIZE;
+        };
+        let mut size = CHUNK_SIZE;
+        if let Limit::BytesPerSec(rate) = limiter.current_limit() {
+            size = size.min(rate);
+        }
+        if let Some((rate, _)) = limiter.cameras.get(&self.camera_id) {
+            size = size.min(*rate);
+        }
+        size.max(1)
+    }
+
+    fn waited(&self, duration: Duration) {
+        metrics::UPLOAD_THROTTLE_WAIT
+            .with_label_values(&[&self.camera_id])
+            .inc_by(duration.as_secs_f64());
+    }
+
+    /// Read `length` bytes of a file from `offset`, paced by this throttle
+    pub fn read_file(
+        &self,
+        path: &Path,
+        offset: u64,
+        length: u64,
+    ) -> impl Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+        let state = (self.clone(), path.to_path_buf(), None, offset, length);
+        stream::try_unfold(
+            state,
+            |(throttle, path, file, offset, remaining): (
+                UploadThrottle,
+                PathBuf,
+                Option<tokio::fs::File>,
+                u64,
+                u64,
+            )| async move {
+                if remaining == 0 {
+                    return Ok(None);
+                }
+                let mut file = match file {
+                    Some(file) => file,
+                    None => {
+                        let mut file = tokio::fs::File::open(&path).await?;
+                        file.seek(SeekFrom::Start(offset)).await?;
+                        file
+                    }
+                };
+
+                let len = remaining.min(throttle.chunk_size());
+                throttle.acquire(len).await;
+                let mut buf = vec![0u8; len as usize];
+                file.read_exact(&mut buf).await?;
+                Ok(Some((
+                    Bytes::from(buf),
+                    (throttle, path, Some(file), offset, remaining - len),
+                )))
+            },
+        )
+    }
+
+    /// HTTP body streaming part of a file through this throttle
+    pub fn body(&self, path: &Path, offset: u64, length: u64) -> ThrottledBody {
+        ThrottledBody {
+            stream: Mutex::new(self.read_file(path, offset, length).boxed()),
+            remaining: length,
+        }
+    }
+}
+
+/// Request body with an exact length, so S3 still gets a Content-Length
+pub struct ThrottledBody {
+    // Mutex only makes the stream Sync; polling uses get_mut and never locks
+    stream: Mutex<Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>>,
+    remaining: u64,
+}
+
+impl Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, std::io::Error>>> {
+        let this = self.get_mut();
+        let stream = this.stream.get_mut().unwrap();
+        match stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.remaining = this.remaining.saturating_sub(chunk.len() as u64);
+                Poll::Ready(Some(Ok(Frame::data(chunk))))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Limiter with only a per-camera cap of `kbps`
+    fn limiter(kbps: u64) -> Arc<BandwidthLimiter> {
+        Arc::new(BandwidthLimiter {
+            default_kbps: None,
+            schedule: Vec::new(),
+            global: TokenBucket::new(),
+            cameras: HashMap::from([(
+                "camera-1".to_string(),
+                (kbps * 1000 / 8, TokenBucket::new()),
+            )]),
+        })
+    }
+
+    #[test]
+    fn chunks_hold_one_second_at_low_rates() {
+        assert_eq!(limiter(8).throttle("camera-1").chunk_size(), 1000);
+        assert_eq!(
+            limiter(100_000).throttle("camera-1").chunk_size(),
+            CHUNK_SIZE
+        );
+        assert_eq!(limiter(8).throttle("camera-2").chunk_size(), CHUNK_SIZE);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_chunk_waits_much_longer_than_a_second() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0u8; 10_000]).unwrap();
+
+        // 8 kbit/s = 1000 bytes/s, far below one 64 KiB chunk per second
+        let throttle = limiter(8).throttle("camera-1");
+        let mut chunks = std::pin::pin!(throttle.read_file(file.path(), 0, 10_000));
+        let started = Instant::now();
+        let mut last = started;
+        let mut read = 0;
+        while let Some(chunk) = chunks.next().await {
+            read += chunk.unwrap().len();
+            let now = Instant::now();
+            assert!(
+                now - last <= Duration::from_millis(1100),
+                "chunk waited {:?}",
+                now - last
+            );
+            last = now;
+        }
+
+        assert_eq!(read, 10_000);
+        assert!(started.elapsed() >= Duration::from_secs(9));
+    }
+}
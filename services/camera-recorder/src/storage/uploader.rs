@@ -1,89 +1,387 @@
-use super::S3Client;
+use super::checksum::{Sha256Digest, SHA256_METADATA_KEY};
+use super::dead_letter::DeadLetters;
+use super::encryption::{self, Keyring};
+use super::journal::{SegmentState, UploadJournal};
+use super::manifest::Manifests;
+use super::metadata::SegmentMetadata;
+use super::priority::UploadPriorities;
+use super::throttle::{BandwidthLimiter, UploadThrottle};
+use super::{multipart, ChecksumMismatch, KeyConflict, KeyLayout, StorageBackend};
+use crate::config::{CameraConfig, Config, ConflictPolicy};
 use crate::metrics;
-use anyhow::Result;
-use chrono::{DateTime, Utc};
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::{mpsc, Semaphore};
+use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
 use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
+/// How often the worker publishes backlog metrics and looks for spilled segments
+const BACKLOG_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// `conflict_policy = "suffix"` gives up after this many taken keys
+const MAX_KEY_SUFFIX: u32 = 100;
+/// Destinations that failed while the rest met the delete policy are retried this often
+const BACKFILL_INTERVAL: Duration = Duration::from_secs(300);
+
 /// Information about a completed segment ready for upload
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SegmentInfo {
     pub camera_id: String,
+    #[serde(default)]
+    pub camera_name: String,
     pub local_path: PathBuf,
-    pub timestamp: DateTime<Utc>,
+    /// When recording started, from the strftime filename
+    #[serde(alias = "timestamp")]
+    pub start: DateTime<Utc>,
+    /// When recording stopped, once the segment is complete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+    /// Media duration, from FFmpeg's segment list or ffprobe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+}
+
+impl SegmentInfo {
+    /// Build segment info from a file named `%Y%m%d_%H%M%S_<camera_id>.mp4`.
+    ///
+    /// Returns `None` for files that don't follow the recorder's naming.
+    pub fn from_file(camera: &CameraConfig, path: &Path) -> Option<Self> {
+        let filename = path.file_name()?.to_str()?;
+        let stem = filename.strip_suffix(".mp4")?;
+        let time_part = stem.strip_suffix(&camera.id)?.strip_suffix('_')?;
+
+        // FFmpeg expands the strftime pattern in local time
+        let naive = NaiveDateTime::parse_from_str(time_part, "%Y%m%d_%H%M%S").ok()?;
+        let start = Local
+            .from_local_datetime(&naive)
+            .earliest()?
+            .with_timezone(&Utc);
+
+        Some(Self {
+            camera_id: camera.id.clone(),
+            camera_name: camera.name.clone(),
+            local_path: path.to_path_buf(),
+            start,
+            end: None,
+            duration_secs: None,
+        })
+    }
+
+    /// Fill in when recording stopped from the segment's media duration
+    pub fn with_duration(mut self, duration_secs: f64) -> Self {
+        self.duration_secs = Some(duration_secs);
+        self.end = chrono::Duration::try_milliseconds((duration_secs * 1000.0) as i64)
+            .map(|duration| self.start + duration);
+        self
+    }
+}
+
+/// Recorder-side handle for queuing completed segments.
+///
+/// Segments are journaled before they are handed to the upload worker, so
+/// a segment is never only in memory.
+#[derive(Clone)]
+pub struct UploadQueue {
+    tx: mpsc::Sender<SegmentInfo>,
+    journal: Arc<UploadJournal>,
+    /// Tells the worker that segments are waiting in the journal only
+    spilled: Arc<Notify>,
+}
+
+/// Worker side of an [`UploadQueue`]
+pub struct UploadReceiver {
+    rx: mpsc::Receiver<SegmentInfo>,
+    spilled: Arc<Notify>,
+}
+
+impl UploadQueue {
+    /// Queue handing up to `capacity` segments to the worker in memory
+    pub fn channel(capacity: usize, journal: Arc<UploadJournal>) -> (Self, UploadReceiver) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let spilled = Arc::new(Notify::new());
+        let queue = Self {
+            tx,
+            journal,
+            spilled: spilled.clone(),
+        };
+        (queue, UploadReceiver { rx, spilled })
+    }
+
+    /// Journal a completed segment and hand it to the upload worker.
+    ///
+    /// Never waits on the worker: when the channel is full the journal entry
+    /// is all the worker gets, and it picks the segment up from there. The
+    /// journal write runs on the blocking pool, so a slow disk stalls only
+    /// the caller and not the runtime threads FFmpeg's tasks share.
+    pub async fn enqueue(&self, segment: SegmentInfo) -> Result<()> {
+        let journal = self.journal.clone();
+        let segment = tokio::task::spawn_blocking(move || {
+            journal
+                .record(&segment, SegmentState::Recorded)
+                .map(|()| segment)
+        })
+        .await
+        .context("Journal task failed")?
+        .context("Failed to journal segment")?;
+        match self.tx.try_send(segment) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(segment)) => {
+                warn!(
+                    camera_id = %segment.camera_id,
+                    path = %segment.local_path.display(),
+                    "Upload channel full, segment left in the journal"
+                );
+                metrics::UPLOAD_HANDOFF_SPILLS
+                    .with_label_values(&[&segment.camera_id])
+                    .inc();
+                self.spilled.notify_one();
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => anyhow::bail!("Upload queue closed"),
+        }
+    }
 }
 
 pub struct UploadWorker {
     rx: mpsc::Receiver<SegmentInfo>,
-    s3_client: S3Client,
+    spilled: Arc<Notify>,
+    queue: UploadPriorities,
+    ctx: Arc<UploadContext>,
+    semaphore: Arc<Semaphore>,
+    /// Permits in `semaphore`, all of which are free once no upload is running
+    upload_slots: u32,
+}
+
+/// Settings and handles shared by every upload task
+struct UploadContext {
+    destinations: Vec<Arc<dyn StorageBackend>>,
+    journal: Arc<UploadJournal>,
     max_retries: u32,
     retry_backoff_secs: u64,
-    semaphore: Arc<Semaphore>,
+    /// Destinations that must hold a segment before the local copy is expendable
+    required_successes: usize,
+    /// How to handle keys that already hold different content
+    conflict_policy: ConflictPolicy,
+    keys: Arc<KeyLayout>,
+    /// Segments are encrypted before upload when a keyring is configured
+    keyring: Option<Arc<Keyring>>,
+    /// Bandwidth caps and upload windows
+    limiter: Arc<BandwidthLimiter>,
+    /// Where segments go once they exhaust their retries
+    dead_letters: Arc<DeadLetters>,
+    /// Per-day indexes of what each destination holds
+    manifests: Arc<Manifests>,
+    keep_local: bool,
 }
 
 impl UploadWorker {
     pub fn new(
-        rx: mpsc::Receiver<SegmentInfo>,
-        s3_client: S3Client,
-        max_concurrent: usize,
-        max_retries: u32,
-        retry_backoff_secs: u64,
+        receiver: UploadReceiver,
+        destinations: Vec<Arc<dyn StorageBackend>>,
+        journal: Arc<UploadJournal>,
+        config: &Config,
+        keys: Arc<KeyLayout>,
+        keyring: Option<Arc<Keyring>>,
+        limiter: Arc<BandwidthLimiter>,
     ) -> Self {
+        let queue = UploadPriorities::new(&config.cameras);
+        let dead_letters = Arc::new(DeadLetters::new(
+            &config.recording.temp_dir,
+            &config.cameras,
+            journal.clone(),
+            queue.clone(),
+        ));
+        let manifests = Arc::new(Manifests::new(
+            destinations.clone(),
+            &config.cameras,
+            journal.clone(),
+        ));
         Self {
-            rx,
-            s3_client,
-            max_retries,
-            retry_backoff_secs,
-            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            rx: receiver.rx,
+            spilled: receiver.spilled,
+            queue,
+            ctx: Arc::new(UploadContext {
+                destinations,
+                journal,
+                max_retries: config.upload.max_retries,
+                retry_backoff_secs: config.upload.retry_backoff_secs,
+                required_successes: config.storage.required_successes(),
+                conflict_policy: config.storage.conflict_policy,
+                keys,
+                keyring,
+                limiter,
+                dead_letters,
+                manifests,
+                keep_local: config.recording.local_retention_minutes > 0,
+            }),
+            semaphore: Arc::new(Semaphore::new(config.upload.max_concurrent)),
+            upload_slots: config.upload.max_concurrent as u32,
         }
     }
 
-    /// Run the upload worker (processes segments from queue)
-    pub async fn run(mut self) {
+    /// Handle for bookmarking segments to upload first
+    pub fn priorities(&self) -> UploadPriorities {
+        self.queue.clone()
+    }
+
+    /// Handle for listing and re-driving failed segments
+    pub fn dead_letters(&self) -> Arc<DeadLetters> {
+        self.ctx.dead_letters.clone()
+    }
+
+    /// Handle for sealing the per-day manifests
+    pub fn manifests(&self) -> Arc<Manifests> {
+        self.ctx.manifests.clone()
+    }
+
+    /// Run the upload worker (processes segments from queue).
+    ///
+    /// Once `drain` is cancelled (or every sender is gone) it stops taking
+    /// new segments, uploads what is already queued and returns when the
+    /// last upload has finished.
+    pub async fn run(mut self, drain: CancellationToken) {
         info!("Upload worker started");
 
-        while let Some(segment) = self.rx.recv().await {
-            let s3_client = self.s3_client.clone();
-            let semaphore = self.semaphore.clone();
-            let max_retries = self.max_retries;
-            let retry_backoff_secs = self.retry_backoff_secs;
-
-            // Spawn upload task (limited by semaphore)
-            tokio::spawn(async move {
-                let _permit = semaphore.acquire().await.unwrap();
-
-                if let Err(e) =
-                    upload_segment_with_retry(segment, s3_client, max_retries, retry_backoff_secs)
-                        .await
-                {
-                    error!(error = %e, "Failed to upload segment after retries");
+        // Replay segments left over from a previous run
+        let pending = self.ctx.journal.pending();
+        if !pending.is_empty() {
+            info!(
+                count = pending.len(),
+                "Re-queuing segments from upload journal"
+            );
+        }
+        for segment in pending {
+            self.queue.push(segment);
+        }
+
+        // Keep draining the channel while every upload slot is busy, so the
+        // queue (not the channel) decides what goes next
+        let mut open = true;
+        let mut ticker = tokio::time::interval(BACKLOG_CHECK_INTERVAL);
+        while open || !self.queue.is_empty() {
+            tokio::select! {
+                biased;
+                received = self.rx.recv(), if open => match received {
+                    Some(segment) => self.queue.push(segment),
+                    None => open = false,
+                },
+                permit = self.semaphore.clone().acquire_owned(), if !self.queue.is_empty() => {
+                    if let Some(segment) = self.queue.pop() {
+                        self.spawn_upload(segment, permit.unwrap());
+                    }
                 }
-            });
+                // Re-driven segments are pushed straight into the queue
+                _ = self.queue.pushed(), if open => {}
+                _ = self.spilled.notified(), if open => self.requeue_spilled(),
+                _ = ticker.tick(), if open => {
+                    // Catches spills whose notification raced with a drain
+                    self.requeue_spilled();
+                    update_backlog_metrics(&self.ctx.journal).await;
+                }
+                _ = drain.cancelled(), if open => {
+                    info!("Draining upload queue");
+                    self.requeue_spilled();
+                    open = false;
+                }
+            }
         }
 
-        info!("Upload worker stopped (channel closed)");
+        // Every upload task holds a permit until it is done
+        let _ = self.semaphore.acquire_many(self.upload_slots).await;
+        info!("Upload worker stopped");
+    }
+
+    /// Queue segments the recorder could only journal because the channel
+    /// was full
+    fn requeue_spilled(&mut self) {
+        // Whatever is still in the channel is already journaled too
+        while let Ok(segment) = self.rx.try_recv() {
+            self.queue.push(segment);
+        }
+        for entry in self.ctx.journal.entries() {
+            if matches!(
+                entry.state,
+                SegmentState::Recorded | SegmentState::Uploading
+            ) {
+                // Queued and in-flight segments are ignored by the queue
+                self.queue.push(entry.segment);
+            }
+        }
+    }
+
+    /// Spawn an upload task holding one of the upload slots
+    fn spawn_upload(&self, segment: SegmentInfo, permit: OwnedSemaphorePermit) {
+        let ctx = self.ctx.clone();
+        let queue = self.queue.clone();
+
+        tokio::spawn(async move {
+            let path = segment.local_path.clone();
+
+            if let Err(e) = upload_segment(segment.clone(), &ctx).await {
+                error!(error = %e, "Failed to upload segment after retries");
+            }
+            queue.finished(&path);
+            drop(permit);
+
+            // Backfill the missing destinations for as long as the local copy exists
+            if ctx
+                .journal
+                .entry(&path)
+                .is_some_and(|e| e.state == SegmentState::Partial)
+            {
+                sleep(BACKFILL_INTERVAL).await;
+                if ctx.journal.contains(&path) {
+                    queue.push(segment);
+                }
+            }
+        });
     }
 }
 
-/// Upload a segment with retry logic
-async fn upload_segment_with_retry(
-    segment: SegmentInfo,
-    s3_client: S3Client,
-    max_retries: u32,
-    retry_backoff_secs: u64,
-) -> Result<()> {
+/// Publish how much is waiting to be uploaded, including spilled segments
+async fn update_backlog_metrics(journal: &UploadJournal) {
+    let pending: Vec<_> = journal
+        .entries()
+        .into_iter()
+        .filter(|e| e.state != SegmentState::Uploaded)
+        .collect();
+
+    let mut bytes = 0;
+    for entry in &pending {
+        if let Ok(metadata) = tokio::fs::metadata(&entry.segment.local_path).await {
+            bytes += metadata.len();
+        }
+    }
+    let oldest_age = pending
+        .iter()
+        .map(|e| e.segment.start)
+        .min()
+        .map_or(0.0, |oldest| {
+            (Utc::now() - oldest).num_milliseconds().max(0) as f64 / 1000.0
+        });
+
+    metrics::UPLOAD_PENDING_SEGMENTS.set(pending.len() as f64);
+    metrics::UPLOAD_PENDING_BYTES.set(bytes as f64);
+    metrics::UPLOAD_OLDEST_PENDING_AGE.set(oldest_age);
+}
+
+/// Upload a segment to every destination that doesn't have it yet
+async fn upload_segment(segment: SegmentInfo, ctx: &UploadContext) -> Result<()> {
     let filename = segment
         .local_path
         .file_name()
         .and_then(|f| f.to_str())
         .unwrap_or("unknown");
 
-    // Build S3 key: camera-{id}/{YYYYMMDD}/{filename}
-    let date_str = segment.timestamp.format("%Y%m%d").to_string();
-    let s3_key = format!("{}/{}/{}", segment.camera_id, date_str, filename);
+    let s3_key = ctx.keys.key_for(&segment);
+
+    // Outside the upload windows segments wait here, already journaled
+    let throttle = ctx.limiter.throttle(&segment.camera_id);
+    throttle.wait_for_window().await;
 
     info!(
         camera_id = %segment.camera_id,
@@ -92,41 +390,245 @@ async fn upload_segment_with_retry(
         "Starting upload"
     );
 
-    let mut retry = 0;
+    let mut metadata = SegmentMetadata::collect(&segment).await;
+
+    // With encryption on, destinations only ever see the encrypted copy
+    let upload_path = match &ctx.keyring {
+        Some(keyring) => match encryption::encrypt_segment(keyring, &segment.local_path).await {
+            Ok((path, key_id)) => {
+                metadata.encryption_key_id = Some(key_id);
+                path
+            }
+            Err(e) => {
+                error!(error = %e, segment = %filename, "Failed to encrypt segment");
+                dead_letter(&segment, &e, ctx).await;
+                return Err(e);
+            }
+        },
+        None => segment.local_path.clone(),
+    };
+
+    if let Err(e) = ctx.journal.record(&segment, SegmentState::Uploading) {
+        warn!(error = %e, segment = %filename, "Failed to journal upload start");
+    }
+
+    // Destinations that already succeeded before a retry or restart are skipped
+    let already_stored = ctx.journal.uploaded_to(&segment.local_path);
+    let backfill = ctx
+        .destinations
+        .iter()
+        .filter(|d| already_stored.contains(d.name()))
+        .count()
+        >= ctx.required_successes;
     let start_time = Instant::now();
 
-    loop {
-        match s3_client.upload_file(&segment.local_path, &s3_key).await {
-            Ok(()) => {
-                let duration = start_time.elapsed().as_secs_f64();
+    let results = join_all(
+        ctx.destinations
+            .iter()
+            .filter(|d| !already_stored.contains(d.name()))
+            .map(|d| {
+                upload_to_destination(
+                    &segment,
+                    &upload_path,
+                    d.as_ref(),
+                    &s3_key,
+                    &metadata,
+                    &throttle,
+                    ctx,
+                )
+            }),
+    )
+    .await;
 
-                info!(
-                    camera_id = %segment.camera_id,
-                    segment = %filename,
-                    s3_key = %s3_key,
-                    duration_secs = duration,
-                    "Upload successful"
-                );
+    let mut stored = ctx
+        .destinations
+        .iter()
+        .filter(|d| already_stored.contains(d.name()))
+        .count();
+    let mut last_error = None;
+    let mut missing = Vec::new();
+    for (destination, result) in ctx
+        .destinations
+        .iter()
+        .filter(|d| !already_stored.contains(d.name()))
+        .zip(results)
+    {
+        match result {
+            Ok(()) => stored += 1,
+            Err(e) => {
+                missing.push(destination.name());
+                last_error = Some(e);
+            }
+        }
+    }
 
-                // Update metrics
-                metrics::SEGMENTS_UPLOADED
-                    .with_label_values(&[&segment.camera_id])
-                    .inc();
-                metrics::UPLOAD_DURATION
-                    .with_label_values(&[&segment.camera_id])
-                    .observe(duration);
+    if stored < ctx.required_successes {
+        let e = last_error.unwrap_or_else(|| anyhow::anyhow!("No storage destinations configured"));
+        error!(
+            error = %e,
+            camera_id = %segment.camera_id,
+            segment = %filename,
+            stored = stored,
+            required = ctx.required_successes,
+            "Upload failed after max retries"
+        );
+        dead_letter(&segment, &e, ctx).await;
+        return Err(e);
+    }
 
-                // Cleanup local file
-                if let Err(e) = s3_client.cleanup_local_file(&segment.local_path).await {
-                    warn!(
-                        error = %e,
-                        path = %segment.local_path.display(),
-                        "Failed to cleanup local file"
+    let duration = start_time.elapsed().as_secs_f64();
+    if let Some(e) = &last_error {
+        for destination in &missing {
+            warn!(
+                error = %e,
+                camera_id = %segment.camera_id,
+                segment = %filename,
+                destination = %destination,
+                stored = stored,
+                total = ctx.destinations.len(),
+                "Destination is missing the segment, will backfill while the local copy exists"
+            );
+        }
+    }
+
+    // A backfill only adds copies of a segment that was already counted
+    if !backfill {
+        info!(
+            camera_id = %segment.camera_id,
+            segment = %filename,
+            s3_key = %s3_key,
+            duration_secs = duration,
+            "Upload successful"
+        );
+
+        // Update metrics
+        metrics::SEGMENTS_UPLOADED
+            .with_label_values(&[&segment.camera_id])
+            .inc();
+        metrics::UPLOAD_DURATION
+            .with_label_values(&[&segment.camera_id])
+            .observe(duration);
+    }
+
+    if ctx.keyring.is_some() {
+        if let Err(e) = encryption::remove_encrypted_copy(&segment.local_path).await {
+            warn!(error = %e, segment = %filename, "Failed to remove encrypted copy");
+        }
+    }
+
+    if !missing.is_empty() {
+        if let Err(e) = ctx.journal.record(&segment, SegmentState::Partial) {
+            warn!(error = %e, segment = %filename, "Failed to journal partial upload");
+        }
+        return Ok(());
+    }
+
+    if let Err(e) = ctx.journal.record(&segment, SegmentState::Uploaded) {
+        warn!(error = %e, segment = %filename, "Failed to journal upload");
+    }
+
+    // Without a retention window there is nothing to keep the local copy for;
+    // otherwise the janitor deletes it once the window has passed
+    if !ctx.keep_local {
+        match cleanup_local_file(&segment.local_path).await {
+            Ok(()) => ctx.journal.forget(&segment.local_path),
+            Err(e) => warn!(
+                error = %e,
+                path = %segment.local_path.display(),
+                "Failed to cleanup local file"
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Record a permanent failure and move the segment to the dead-letter area
+async fn dead_letter(segment: &SegmentInfo, error: &anyhow::Error, ctx: &UploadContext) {
+    let error = format!("{error:#}");
+    if let Err(e) = ctx.journal.record_failure(segment, &error) {
+        warn!(error = %e, path = %segment.local_path.display(), "Failed to journal upload failure");
+    }
+    if let Err(e) = ctx.dead_letters.add(segment, &error).await {
+        // The journal still has it, so the next restart retries it
+        warn!(error = %e, path = %segment.local_path.display(), "Failed to dead-letter segment");
+    }
+}
+
+/// Upload a segment to one destination, with its own retry and backoff
+async fn upload_to_destination(
+    segment: &SegmentInfo,
+    upload_path: &Path,
+    backend: &dyn StorageBackend,
+    s3_key: &str,
+    metadata: &SegmentMetadata,
+    throttle: &UploadThrottle,
+    ctx: &UploadContext,
+) -> Result<()> {
+    let destination = backend.name();
+    let mut retry = 0;
+    let start_time = Instant::now();
+    // Only hashed if an object of the same size is already there
+    let mut digest = None;
+
+    loop {
+        let attempt = async {
+            match resolve_target(
+                backend,
+                upload_path,
+                s3_key,
+                ctx.conflict_policy,
+                &mut digest,
+            )
+            .await?
+            {
+                Target::Stored(key) => Ok::<_, anyhow::Error>((key, "existing")),
+                Target::Upload(key) => {
+                    backend.put(upload_path, &key, metadata, throttle).await?;
+                    Ok((key, "success"))
+                }
+            }
+        };
+        match attempt.await {
+            Ok((key, result)) => {
+                metrics::DESTINATION_UPLOADS
+                    .with_label_values(&[&segment.camera_id, destination, result])
+                    .inc();
+                metrics::DESTINATION_UPLOAD_DURATION
+                    .with_label_values(&[destination])
+                    .observe(start_time.elapsed().as_secs_f64());
+                if result == "existing" {
+                    info!(
+                        camera_id = %segment.camera_id,
+                        destination = %destination,
+                        s3_key = %key,
+                        "Identical object already stored, skipping upload"
                     );
                 }
 
+                if let Err(e) = ctx.journal.record_destination(segment, destination) {
+                    warn!(error = %e, destination = %destination, "Failed to journal destination upload");
+                }
+                // The segment is safely stored; a stale manifest is fixed by the next update
+                if let Err(e) = ctx.manifests.record(backend, &key, metadata).await {
+                    warn!(error = %e, destination = %destination, s3_key = %key, "Failed to update manifest");
+                }
                 return Ok(());
             }
+            Err(e) if e.downcast_ref::<KeyConflict>().is_some() => {
+                // Retrying can't make the existing object go away
+                metrics::DESTINATION_UPLOADS
+                    .with_label_values(&[&segment.camera_id, destination, "conflict"])
+                    .inc();
+                error!(
+                    error = %e,
+                    camera_id = %segment.camera_id,
+                    destination = %destination,
+                    s3_key = %s3_key,
+                    "Key conflict, not uploading"
+                );
+                return Err(e.context(format!("Upload to {destination} failed")));
+            }
             Err(e) => {
                 retry += 1;
 
@@ -134,23 +636,33 @@ async fn upload_segment_with_retry(
                 metrics::UPLOAD_FAILURES
                     .with_label_values(&[&segment.camera_id])
                     .inc();
+                metrics::DESTINATION_UPLOADS
+                    .with_label_values(&[&segment.camera_id, destination, "failure"])
+                    .inc();
+                if e.downcast_ref::<ChecksumMismatch>().is_some() {
+                    metrics::CHECKSUM_MISMATCHES
+                        .with_label_values(&[&segment.camera_id])
+                        .inc();
+                }
 
-                if retry >= max_retries {
+                if retry >= ctx.max_retries {
                     error!(
                         error = %e,
                         camera_id = %segment.camera_id,
-                        segment = %filename,
+                        destination = %destination,
+                        s3_key = %s3_key,
                         retries = retry,
-                        "Upload failed after max retries"
+                        "Upload to destination failed after max retries"
                     );
-                    return Err(e);
+                    return Err(e.context(format!("Upload to {destination} failed")));
                 }
 
-                let wait_secs = retry_backoff_secs * 2u64.pow(retry - 1);
+                let wait_secs = ctx.retry_backoff_secs * 2u64.pow(retry - 1);
                 warn!(
                     error = %e,
                     camera_id = %segment.camera_id,
-                    segment = %filename,
+                    destination = %destination,
+                    s3_key = %s3_key,
                     retry = retry,
                     wait_secs = wait_secs,
                     "Upload failed, retrying"
@@ -160,3 +672,100 @@ async fn upload_segment_with_retry(
         }
     }
 }
+
+/// Where a segment goes on one destination
+enum Target {
+    /// The key already holds exactly this file
+    Stored(String),
+    /// Upload to this key
+    Upload(String),
+}
+
+/// Pick the key to upload to, or find that an identical object is already
+/// stored, comparing size and then SHA-256 with any existing object
+async fn resolve_target(
+    backend: &dyn StorageBackend,
+    upload_path: &Path,
+    key: &str,
+    policy: ConflictPolicy,
+    digest: &mut Option<Sha256Digest>,
+) -> Result<Target> {
+    let size = tokio::fs::metadata(upload_path)
+        .await
+        .context("Failed to stat segment")?
+        .len();
+    let mut candidate = key.to_string();
+    let mut suffix = 0;
+
+    loop {
+        let Some(existing) = backend.head(&candidate).await? else {
+            return Ok(Target::Upload(candidate));
+        };
+        let detail = if existing.size != size {
+            format!("size {} != {size}", existing.size)
+        } else {
+            if digest.is_none() {
+                *digest = Some(Sha256Digest::of_file(upload_path).await?);
+            }
+            let local = digest
+                .as_ref()
+                .map(Sha256Digest::to_hex)
+                .unwrap_or_default();
+            match existing.metadata.get(SHA256_METADATA_KEY) {
+                Some(remote) if *remote == local => return Ok(Target::Stored(candidate)),
+                Some(remote) => format!("sha256 {remote} != {local}"),
+                None => "no sha256 metadata to compare".to_string(),
+            }
+        };
+
+        match policy {
+            ConflictPolicy::Overwrite => {
+                warn!(
+                    s3_key = %candidate,
+                    destination = %backend.name(),
+                    detail = %detail,
+                    "Overwriting existing object with different content"
+                );
+                return Ok(Target::Upload(candidate));
+            }
+            ConflictPolicy::Fail => {
+                return Err(KeyConflict {
+                    key: candidate,
+                    detail,
+                }
+                .into())
+            }
+            ConflictPolicy::Suffix => {
+                suffix += 1;
+                anyhow::ensure!(
+                    suffix <= MAX_KEY_SUFFIX,
+                    "No free key for {key} after {MAX_KEY_SUFFIX} suffixes"
+                );
+                candidate = with_suffix(key, suffix);
+            }
+        }
+    }
+}
+
+/// `camera-1/20251214/013000_camera-1.mp4` with suffix 1 -> `camera-1/20251214/013000_camera-1-1.mp4`
+fn with_suffix(key: &str, suffix: u32) -> String {
+    let name_start = key.rfind('/').map_or(0, |i| i + 1);
+    match key[name_start..].rfind('.').filter(|&i| i > 0) {
+        Some(dot) => {
+            let dot = name_start + dot;
+            format!("{}-{suffix}{}", &key[..dot], &key[dot..])
+        }
+        None => format!("{key}-{suffix}"),
+    }
+}
+
+/// Delete a local segment (and any multipart progress or encrypted copy) after successful upload
+pub async fn cleanup_local_file(path: &Path) -> Result<()> {
+    tokio::fs::remove_file(path)
+        .await
+        .context("Failed to delete local file")?;
+    multipart::remove_all_state(path).await?;
+    encryption::remove_encrypted_copy(path).await?;
+    info!(path = %path.display(), "Cleaned up local file");
+    Ok(())
+}